@@ -0,0 +1,139 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdsrStage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Linear attack, decay, sustain, release envelope generator. Times are in milliseconds and the
+/// sustain level is a gain between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    pub attack_ms: f32,
+    pub decay_ms: f32,
+    pub sustain: f32,
+    pub release_ms: f32,
+    stage: AdsrStage,
+    level: f32,
+    release_step: f32,
+}
+
+impl Adsr {
+    pub fn new(attack_ms: f32, decay_ms: f32, sustain: f32, release_ms: f32) -> Self {
+        Self {
+            attack_ms,
+            decay_ms,
+            sustain,
+            release_ms,
+            stage: AdsrStage::Idle,
+            level: 0.0,
+            release_step: 0.0,
+        }
+    }
+
+    pub fn stage(&self) -> AdsrStage {
+        self.stage
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != AdsrStage::Idle
+    }
+
+    /// Start the attack from the current level, so retriggering doesn't click.
+    pub fn note_on(&mut self) {
+        self.stage = AdsrStage::Attack;
+    }
+
+    /// Start the release from the current level.
+    pub fn note_off(&mut self, sample_rate: f32) {
+        if self.stage != AdsrStage::Idle {
+            self.stage = AdsrStage::Release;
+            self.release_step = self.level / time_to_samples(self.release_ms, sample_rate);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stage = AdsrStage::Idle;
+        self.level = 0.0;
+    }
+
+    pub fn next(&mut self, sample_rate: f32) -> f32 {
+        match self.stage {
+            AdsrStage::Idle => {}
+            AdsrStage::Attack => {
+                self.level += 1.0 / time_to_samples(self.attack_ms, sample_rate);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = AdsrStage::Decay;
+                }
+            }
+            AdsrStage::Decay => {
+                self.level -= (1.0 - self.sustain) / time_to_samples(self.decay_ms, sample_rate);
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = AdsrStage::Sustain;
+                }
+            }
+            AdsrStage::Sustain => self.level = self.sustain,
+            AdsrStage::Release => {
+                self.level -= self.release_step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = AdsrStage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+fn time_to_samples(time_ms: f32, sample_rate: f32) -> f32 {
+    (time_ms * 0.001 * sample_rate).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adsr_stages() {
+        // 10 samples attack, 10 samples decay to 0.5, 20 samples release at 1 kHz
+        let mut adsr = Adsr::new(10.0, 10.0, 0.5, 20.0);
+        assert_eq!(adsr.next(1000.0), 0.0);
+
+        adsr.note_on();
+        let attack: Vec<f32> = (0..10).map(|_| adsr.next(1000.0)).collect();
+        assert!(attack.windows(2).all(|w| w[1] > w[0]));
+        assert_eq!(adsr.level(), 1.0);
+
+        for _ in 0..20 {
+            adsr.next(1000.0);
+        }
+        assert_eq!(adsr.stage(), AdsrStage::Sustain);
+        assert_eq!(adsr.level(), 0.5);
+
+        adsr.note_off(1000.0);
+        assert!((adsr.next(1000.0) - 0.475).abs() < 1e-6);
+        for _ in 0..19 {
+            adsr.next(1000.0);
+        }
+        assert!(!adsr.is_active());
+        assert_eq!(adsr.level(), 0.0);
+    }
+
+    #[test]
+    fn test_adsr_zero_times() {
+        let mut adsr = Adsr::new(0.0, 0.0, 1.0, 0.0);
+        adsr.note_on();
+        assert_eq!(adsr.next(44100.0), 1.0);
+        adsr.note_off(44100.0);
+        assert_eq!(adsr.next(44100.0), 0.0);
+    }
+}
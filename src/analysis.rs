@@ -0,0 +1,58 @@
+//! Offline spectral measurements, intended for metering and display rather than the audio path.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f32::consts::PI;
+
+/// Magnitude weighted mean frequency of `samples` in Hz. A Hann window is applied before the FFT
+/// to keep leakage from pulling the result towards the middle of the spectrum. Silence yields 0.
+pub fn spectral_centroid(samples: &[f32], sample_rate: f32) -> f32 {
+    let len = samples.len();
+    if len < 2 {
+        return 0.0;
+    }
+
+    let mut buffer: Vec<Complex<f32>> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &sample)| {
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / (len - 1) as f32).cos();
+            Complex::new(sample * window, 0.0)
+        })
+        .collect();
+    FftPlanner::new().plan_fft_forward(len).process(&mut buffer);
+
+    let bin_hz = sample_rate / len as f32;
+    let mut weighted = 0.0;
+    let mut total = 0.0;
+    for (bin, value) in buffer[..=len / 2].iter().enumerate() {
+        let magnitude = value.norm();
+        weighted += magnitude * bin as f32 * bin_hz;
+        total += magnitude;
+    }
+    if total <= f32::EPSILON {
+        0.0
+    } else {
+        weighted / total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sine_wave;
+
+    fn sine(freq: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|i| sine_wave(freq, sample_rate, i)).collect()
+    }
+
+    #[test]
+    fn test_spectral_centroid_tracks_sine_frequency() {
+        let sr = 8000.0;
+        let low = spectral_centroid(&sine(100.0, sr, 1024), sr);
+        let high = spectral_centroid(&sine(2000.0, sr, 1024), sr);
+        assert!((low - 100.0).abs() < 30.0, "low centroid {low}");
+        assert!((high - 2000.0).abs() < 30.0, "high centroid {high}");
+        assert_eq!(spectral_centroid(&[0.0; 256], sr), 0.0);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::envelope::EnvelopeFollower;
+use nih_plug::util;
+
+/// Attack time of the compressor's detectors.
+pub const COMPRESSOR_ATTACK_MS: f32 = 10.0;
+/// Release time of the compressor's detectors.
+pub const COMPRESSOR_RELEASE_MS: f32 = 100.0;
+
+/// Feed forward peak compressor. Linked, every channel gets the gain computed from the loudest
+/// one so the stereo image stays put, otherwise each channel is compressed on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compressor {
+    followers: Vec<EnvelopeFollower>,
+    /// Gain applied to every channel on the last processed sample.
+    gains: Vec<f32>,
+    sample_rate: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            followers: Vec::new(),
+            gains: Vec::new(),
+            sample_rate,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for follower in &mut self.followers {
+            follower.reset();
+        }
+        self.gains.fill(1.0);
+    }
+
+    /// Gain reduction on `channel` in dB during the last processed sample, `0` while the level
+    /// stays below the threshold.
+    pub fn gain_reduction_db(&self, channel: usize) -> f32 {
+        -util::gain_to_db(self.gains.get(channel).copied().unwrap_or(1.0))
+    }
+
+    /// Compress everything above `threshold_db` by `ratio`.
+    pub fn process(
+        &mut self,
+        slices: &mut [&mut [f32]],
+        threshold_db: f32,
+        ratio: f32,
+        stereo_link: bool,
+    ) {
+        let follower = EnvelopeFollower::new(
+            COMPRESSOR_ATTACK_MS,
+            COMPRESSOR_RELEASE_MS,
+            self.sample_rate,
+        );
+        self.followers.resize(slices.len(), follower);
+        self.gains.resize(slices.len(), 1.0);
+        let slope = 1.0 - 1.0 / ratio.max(1.0);
+        let gain_for = |level: f32| {
+            let over = util::gain_to_db(level) - threshold_db;
+            util::db_to_gain(-over.max(0.0) * slope)
+        };
+
+        let num_samples = slices.first().map_or(0, |channel| channel.len());
+        for i in 0..num_samples {
+            if stereo_link {
+                let peak = slices
+                    .iter()
+                    .fold(0.0_f32, |peak, channel| peak.max(channel[i].abs()));
+                let gain = gain_for(self.followers[0].next(peak));
+                self.gains.fill(gain);
+            } else {
+                for ((channel, follower), gain) in
+                    slices.iter().zip(&mut self.followers).zip(&mut self.gains)
+                {
+                    *gain = gain_for(follower.next(channel[i]));
+                }
+            }
+            for (channel, gain) in slices.iter_mut().zip(&self.gains) {
+                channel[i] *= gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressor_reduces_gain_above_threshold() {
+        let mut compressor = Compressor::new(1000.0);
+        let mut quiet = vec![0.1; 1000];
+        compressor.process(&mut [&mut quiet], -12.0, 4.0, true);
+        assert!(quiet.iter().all(|&s| s == 0.1));
+        assert_eq!(compressor.gain_reduction_db(0), 0.0);
+
+        // 12 dB over the threshold at 4:1 leaves 3 dB, a 9 dB reduction
+        let mut loud = vec![util::db_to_gain(0.0); 1000];
+        compressor.process(&mut [&mut loud], -12.0, 4.0, true);
+        assert!((compressor.gain_reduction_db(0) - 9.0).abs() < 0.05);
+        assert!((util::gain_to_db(loud[999]) + 9.0).abs() < 0.05);
+    }
+}
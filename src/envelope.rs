@@ -1,26 +1,346 @@
-pub fn envelope_follower(samples: &[f32], attack_ms: f32, release_ms: f32, sample_rate: f32) -> Vec<f32> {
-    let attack_coeff = if attack_ms <= 0.0 {
+use nih_plug::prelude::Enum;
+
+/// Values below this are flushed to zero in recursive state updates so long decays never end up
+/// in the (slow on some CPUs) denormal range.
+const DENORMAL_THRESHOLD: f32 = 1e-15;
+
+/// Lowest level the log domain detector goes down to, anything quieter counts as silence.
+pub const LOG_FLOOR_DB: f32 = -100.0;
+
+pub(crate) fn flush_denormal(value: f32) -> f32 {
+    if value.abs() < DENORMAL_THRESHOLD {
+        0.0
+    } else {
+        value
+    }
+}
+
+/// One pole smoothing coefficient that covers about 90% of a step after `time_ms`. A time of zero
+/// jumps straight to the target.
+pub fn smoothing_coeff(time_ms: f32, sample_rate: f32) -> f32 {
+    sample_coefficient(time_ms * 0.001 * sample_rate)
+}
+
+/// The attack and release coefficients `EnvelopeFollower::new` uses for these times.
+pub(crate) fn follower_coefficients(
+    attack_ms: f32,
+    release_ms: f32,
+    sample_rate: f32,
+) -> (f32, f32) {
+    (
+        smoothing_coeff(attack_ms, sample_rate),
+        smoothing_coeff(release_ms, sample_rate),
+    )
+}
+
+pub(crate) fn sample_coefficient(time_samples: f32) -> f32 {
+    if time_samples <= 0.0 {
         1.0
     } else {
-        let attack_samples = attack_ms * 0.001 * sample_rate;
-        1.0 - (-2.2_f32 / attack_samples).exp()
-    };
-    let release_coeff = if release_ms <= 0.0 {
+        1.0 - (-2.2_f32 / time_samples).exp()
+    }
+}
+
+/// One pole coefficient that halves the distance to the target, a 6 dB step, every `time_ms`.
+/// A time of zero jumps straight to the target.
+pub fn six_db_coeff(time_ms: f32, sample_rate: f32) -> f32 {
+    let time_samples = time_ms * 0.001 * sample_rate;
+    if time_samples <= 0.0 {
         1.0
     } else {
+        1.0 - 0.5_f32.powf(1.0 / time_samples)
+    }
+}
+
+/// What the envelope follower measures.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetectorMode {
+    /// Follows the rectified signal, reacts to every peak.
+    #[default]
+    Peak,
+    /// Follows the mean square and returns its root, closer to perceived loudness.
+    #[name = "RMS"]
+    Rms,
+    /// Follows the rectified signal in decibels, so it moves by the same number of dB in the
+    /// same time no matter how loud the signal is.
+    Log,
+}
+
+/// Shape of the follower's release.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReleaseCurve {
+    /// Falls at a constant rate and reaches silence after the release time.
+    Linear,
+    /// One pole decay, fast at first and slowing down as it approaches the input level.
+    #[default]
+    Exponential,
+    /// Eases out of the peak and into silence, which pumps more smoothly when ducking.
+    #[name = "S-Curve"]
+    SCurve,
+}
+
+fn level_to_db(level: f32) -> f32 {
+    (20.0 * level.log10()).max(LOG_FLOOR_DB)
+}
+
+/// Stateful version of `envelope_follower` that keeps its level between calls, so processing a
+/// signal in blocks gives the same result as processing it at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeFollower {
+    attack_coeff: f32,
+    release_coeff: f32,
+    mode: DetectorMode,
+    env: f32,
+    release_curve: ReleaseCurve,
+    /// Progress through a linear or S-curve release per sample.
+    release_step: f32,
+    /// Level the current linear or S-curve release started from.
+    release_start: f32,
+    /// Samples into the current linear or S-curve release, `0` when none is running.
+    release_elapsed: u32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(attack_ms: f32, release_ms: f32, sample_rate: f32) -> Self {
+        let (attack_coeff, release_coeff) =
+            follower_coefficients(attack_ms, release_ms, sample_rate);
+        Self::from_coefficients(attack_coeff, release_coeff)
+    }
+
+    pub fn builder() -> EnvelopeFollowerBuilder {
+        EnvelopeFollowerBuilder::default()
+    }
+
+    /// Fast peak detection that keeps the punch of drum hits.
+    pub fn kick(sample_rate: f32) -> Self {
+        Self::builder()
+            .attack_ms(1.0)
+            .release_ms(60.0)
+            .sample_rate(sample_rate)
+            .build()
+    }
+
+    /// Slow RMS detection for sustained material, with little ripple and pumping.
+    pub fn sustain(sample_rate: f32) -> Self {
+        Self::builder()
+            .attack_ms(20.0)
+            .release_ms(300.0)
+            .mode(DetectorMode::Rms)
+            .sample_rate(sample_rate)
+            .build()
+    }
+
+    fn from_coefficients(attack_coeff: f32, release_coeff: f32) -> Self {
+        Self {
+            attack_coeff,
+            release_coeff,
+            mode: DetectorMode::Peak,
+            env: 0.0,
+            release_curve: ReleaseCurve::Exponential,
+            release_step: 1.0,
+            release_start: 0.0,
+            release_elapsed: 0,
+        }
+    }
+
+    /// Change the attack and release times while keeping the current level.
+    pub fn set_times(&mut self, attack_ms: f32, release_ms: f32, sample_rate: f32) {
+        (self.attack_coeff, self.release_coeff) =
+            follower_coefficients(attack_ms, release_ms, sample_rate);
+    }
+
+    /// Like `set_times`, but with the times it takes the level to move by 6 dB.
+    pub fn set_6db_times(&mut self, attack_ms: f32, release_ms: f32, sample_rate: f32) {
+        self.attack_coeff = six_db_coeff(attack_ms, sample_rate);
+        self.release_coeff = six_db_coeff(release_ms, sample_rate);
+    }
+
+    /// Use `curve` for the release. The linear and S-curve releases fall from the last peak to
+    /// silence in `release_ms` unless the input holds them up, the exponential one keeps the
+    /// release coefficient from `set_times` or `set_6db_times`.
+    pub fn set_release_curve(&mut self, curve: ReleaseCurve, release_ms: f32, sample_rate: f32) {
+        self.release_curve = curve;
         let release_samples = release_ms * 0.001 * sample_rate;
-        1.0 - (-2.2_f32 / release_samples).exp()
-    };
+        self.release_step = if release_samples > 1.0 { 1.0 / release_samples } else { 1.0 };
+    }
+
+    pub fn mode(&self) -> DetectorMode {
+        self.mode
+    }
 
+    /// Switch what the follower measures while keeping the current level.
+    pub fn set_mode(&mut self, mode: DetectorMode) {
+        if mode != self.mode {
+            let level = self.value();
+            self.mode = mode;
+            self.env = match mode {
+                DetectorMode::Peak => level,
+                DetectorMode::Rms => level * level,
+                DetectorMode::Log => level_to_db(level),
+            };
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        match self.mode {
+            DetectorMode::Peak => self.env,
+            DetectorMode::Rms => self.env.sqrt(),
+            DetectorMode::Log if self.env <= LOG_FLOOR_DB => 0.0,
+            DetectorMode::Log => 10.0_f32.powf(self.env / 20.0),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.env = match self.mode {
+            DetectorMode::Log => LOG_FLOOR_DB,
+            _ => 0.0,
+        };
+        self.release_elapsed = 0;
+    }
+
+    pub fn next(&mut self, sample: f32) -> f32 {
+        let target = match self.mode {
+            DetectorMode::Peak => sample.abs(),
+            DetectorMode::Rms => sample * sample,
+            DetectorMode::Log => level_to_db(sample.abs()),
+        };
+        if target > self.env {
+            self.env += self.attack_coeff * (target - self.env);
+            self.release_elapsed = 0;
+        } else if self.release_curve == ReleaseCurve::Exponential {
+            self.env += self.release_coeff * (target - self.env);
+        } else {
+            if self.release_elapsed == 0 {
+                self.release_start = self.env;
+            }
+            self.release_elapsed = self.release_elapsed.saturating_add(1);
+            let pos = (self.release_elapsed as f32 * self.release_step).min(1.0);
+            let shape = match self.release_curve {
+                ReleaseCurve::SCurve => pos * pos * (3.0 - 2.0 * pos),
+                _ => pos,
+            };
+            let floor = match self.mode {
+                DetectorMode::Log => LOG_FLOOR_DB,
+                _ => 0.0,
+            };
+            let released = self.release_start + shape * (floor - self.release_start);
+            if released > target {
+                self.env = released;
+            } else {
+                // The input holds the level up, the next release starts from wherever it is then
+                self.env = target;
+                self.release_elapsed = 0;
+            }
+        }
+        self.env = flush_denormal(self.env);
+        self.value()
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        samples.iter().map(|&s| self.next(s)).collect()
+    }
+}
+
+/// Builder for an `EnvelopeFollower`, the defaults match the plugin's own detector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeFollowerBuilder {
+    attack_ms: f32,
+    release_ms: f32,
+    mode: DetectorMode,
+    sample_rate: f32,
+}
+
+impl Default for EnvelopeFollowerBuilder {
+    fn default() -> Self {
+        Self {
+            attack_ms: 10.0,
+            release_ms: 10.0,
+            mode: DetectorMode::Peak,
+            sample_rate: 44100.0,
+        }
+    }
+}
+
+impl EnvelopeFollowerBuilder {
+    pub fn attack_ms(mut self, attack_ms: f32) -> Self {
+        self.attack_ms = attack_ms;
+        self
+    }
+
+    pub fn release_ms(mut self, release_ms: f32) -> Self {
+        self.release_ms = release_ms;
+        self
+    }
+
+    pub fn mode(mut self, mode: DetectorMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: f32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn build(self) -> EnvelopeFollower {
+        let mut follower = EnvelopeFollower::new(self.attack_ms, self.release_ms, self.sample_rate);
+        follower.set_mode(self.mode);
+        follower
+    }
+}
+
+pub fn envelope_follower(samples: &[f32], attack_ms: f32, release_ms: f32, sample_rate: f32) -> Vec<f32> {
+    EnvelopeFollower::new(attack_ms, release_ms, sample_rate).process(samples)
+}
+
+/// Same as `envelope_follower`, but with the attack and release times given in samples.
+pub fn envelope_follower_samples(samples: &[f32], attack_samples: usize, release_samples: usize) -> Vec<f32> {
+    EnvelopeFollower::from_coefficients(
+        sample_coefficient(attack_samples as f32),
+        sample_coefficient(release_samples as f32),
+    )
+    .process(samples)
+}
+
+/// Envelope follower that attacks with `fast_ms` on transients and with `slow_ms` on sustained
+/// material. How transient the input is gets estimated by comparing a fast and a slow detector.
+pub fn envelope_follower_adaptive(
+    samples: &[f32],
+    fast_ms: f32,
+    slow_ms: f32,
+    release_ms: f32,
+    sample_rate: f32,
+) -> Vec<f32> {
+    let fast_coeff = smoothing_coeff(fast_ms, sample_rate);
+    let slow_coeff = smoothing_coeff(slow_ms, sample_rate);
+    let release_coeff = smoothing_coeff(release_ms, sample_rate);
+
+    let mut fast_env = 0.0_f32;
+    let mut slow_env = 0.0_f32;
     let mut env = 0.0_f32;
     let mut curve = Vec::with_capacity(samples.len());
     for &s in samples {
         let target = s.abs();
+        for (detector, attack) in [(&mut fast_env, fast_coeff), (&mut slow_env, slow_coeff)] {
+            let coeff = if target > *detector { attack } else { release_coeff };
+            *detector = flush_denormal(*detector + coeff * (target - *detector));
+        }
+
+        // 1.0 while the input is rising much faster than the slow detector can follow, 0.0 once
+        // both detectors agree
+        let transient = if fast_env > 0.0 {
+            ((fast_env - slow_env) / fast_env).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let attack_coeff = slow_coeff + transient * (fast_coeff - slow_coeff);
+
         if target > env {
             env += attack_coeff * (target - env);
         } else {
             env += release_coeff * (target - env);
         }
+        env = flush_denormal(env);
         curve.push(env);
     }
     curve
@@ -37,6 +357,15 @@ pub fn apply_gain_curve(samples: &mut [f32], curve: &[f32]) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_smoothing_coeff() {
+        assert_eq!(smoothing_coeff(0.0, 44100.0), 1.0);
+        // 10 ms at 1 kHz is 10 samples
+        assert!((smoothing_coeff(10.0, 1000.0) - (1.0 - (-0.22_f32).exp())).abs() < 1e-6);
+        assert!((smoothing_coeff(100.0, 1000.0) - (1.0 - (-0.022_f32).exp())).abs() < 1e-6);
+        assert!(smoothing_coeff(100.0, 44100.0) < smoothing_coeff(10.0, 44100.0));
+    }
+
     #[test]
     fn test_envelope_follower_constant() {
         let samples = vec![1.0_f32; 100];
@@ -52,10 +381,15 @@ mod tests {
         let mut samples = vec![0.0_f32; 50];
         samples.extend(vec![1.0_f32; 50]);
         let curve = envelope_follower(&samples, 10.0, 10.0, 1000.0);
-        // Check initial silence region stays near zero
-        assert!(curve[25] < 0.1);
-        // The envelope should rise but not reach one immediately
-        assert!(curve[55] > 0.2 && curve[55] < 1.0);
+        assert!(curve[..50].iter().all(|&s| s == 0.0));
+        // One pole step response, `1 - (1 - coeff)^n` after n samples
+        let (attack, _) = follower_coefficients(10.0, 10.0, 1000.0);
+        for (n, &s) in curve[50..].iter().enumerate() {
+            assert!((s - (1.0 - (1.0 - attack).powi(n as i32 + 1))).abs() < 1e-5);
+        }
+        // 10 samples cover 2.2 time constants
+        let tau = -1.0 / (1.0 - attack).ln();
+        assert!((tau - 10.0 / 2.2).abs() < 1e-4);
     }
 
     #[test]
@@ -64,10 +398,126 @@ mod tests {
         let mut samples = vec![1.0_f32; 50];
         samples.extend(vec![0.0_f32; 50]);
         let curve = envelope_follower(&samples, 1.0, 20.0, 1000.0);
-        // Attack region should be close to one
-        assert!(curve[10] > 0.8);
-        // After release period the value should have decreased
-        assert!(curve[90] < 0.3);
+        let (attack, release) = follower_coefficients(1.0, 20.0, 1000.0);
+        // The first sample covers `coeff` of the step
+        assert!((curve[0] - attack).abs() < 1e-6);
+        assert!(curve[10] > 1.0 - 1e-6);
+        for (n, &s) in curve[50..].iter().enumerate() {
+            assert!((s - curve[49] * (1.0 - release).powi(n as i32 + 1)).abs() < 1e-5);
+        }
+        let tau = -1.0 / (1.0 - release).ln();
+        assert!((tau - 20.0 / 2.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_envelope_follower_samples_instant_attack() {
+        let mut samples = vec![0.0_f32; 10];
+        samples.extend(vec![1.0_f32; 10]);
+        let curve = envelope_follower_samples(&samples, 0, 10);
+        assert_eq!(curve[9], 0.0);
+        assert_eq!(curve[10], 1.0);
+    }
+
+    #[test]
+    fn test_envelope_follower_samples_matches_ms() {
+        // 10 ms at 1 kHz is 10 samples
+        let mut samples = vec![1.0_f32; 50];
+        samples.extend(vec![0.0_f32; 50]);
+        let from_ms = envelope_follower(&samples, 10.0, 20.0, 1000.0);
+        let from_samples = envelope_follower_samples(&samples, 10, 20);
+        for (a, b) in from_ms.iter().zip(&from_samples) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_envelope_follower_flushes_denormals() {
+        // An exponentially decaying tail would otherwise leave the state in the denormal range
+        let samples: Vec<f32> = (0..5000).map(|i| 0.5_f32.powi(i)).collect();
+        let curve = envelope_follower(&samples, 1.0, 50.0, 1000.0);
+        assert!(curve.iter().all(|&v| v == 0.0 || v.is_normal()));
+        assert_eq!(*curve.last().unwrap(), 0.0);
+
+        let curve = envelope_follower_adaptive(&samples, 1.0, 10.0, 50.0, 1000.0);
+        assert!(curve.iter().all(|&v| v == 0.0 || v.is_normal()));
+        assert_eq!(*curve.last().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_envelope_follower_adaptive_smoother_on_sustain() {
+        // A sustained low sine makes the plain follower ripple with every half cycle, the
+        // adaptive follower should settle on its slow attack and ripple less
+        let sample_rate = 1000.0;
+        let samples: Vec<f32> = (0..2000)
+            .map(|i| (2.0 * std::f32::consts::PI * 20.0 * i as f32 / sample_rate).sin())
+            .collect();
+        let plain = envelope_follower(&samples, 5.0, 50.0, sample_rate);
+        let adaptive = envelope_follower_adaptive(&samples, 5.0, 50.0, 50.0, sample_rate);
+
+        let ripple = |curve: &[f32]| -> f32 {
+            curve[1000..].windows(2).map(|w| (w[1] - w[0]).abs()).sum()
+        };
+        assert!(ripple(&adaptive) < ripple(&plain));
+    }
+
+    #[test]
+    fn test_envelope_follower_adaptive_fast_on_transients() {
+        // A step from silence is a transient and should be followed about as fast as the plain
+        // follower with the fast attack time
+        let mut samples = vec![0.0_f32; 50];
+        samples.extend(vec![1.0_f32; 50]);
+        let plain_slow = envelope_follower(&samples, 50.0, 50.0, 1000.0);
+        let adaptive = envelope_follower_adaptive(&samples, 5.0, 50.0, 50.0, 1000.0);
+        assert!(adaptive[55] > plain_slow[55]);
+    }
+
+    #[test]
+    fn test_envelope_follower_state_carries_over() {
+        let samples: Vec<f32> = (0..200).map(|i| (i as f32 * 0.3).sin()).collect();
+        let whole = envelope_follower(&samples, 5.0, 20.0, 1000.0);
+        let mut follower = EnvelopeFollower::new(5.0, 20.0, 1000.0);
+        let mut blocks = follower.process(&samples[..64]);
+        blocks.extend(follower.process(&samples[64..]));
+        assert_eq!(blocks, whole);
+        assert_eq!(follower.value(), *whole.last().unwrap());
+    }
+
+    #[test]
+    fn test_envelope_follower_builder_and_presets() {
+        // 5 ms at 1 kHz is 5 samples, 80 ms is 80 samples
+        let follower = EnvelopeFollower::builder()
+            .attack_ms(5.0)
+            .release_ms(80.0)
+            .mode(DetectorMode::Rms)
+            .sample_rate(1000.0)
+            .build();
+        assert!((follower.attack_coeff - (1.0 - (-2.2_f32 / 5.0).exp())).abs() < 1e-6);
+        assert!((follower.release_coeff - (1.0 - (-2.2_f32 / 80.0).exp())).abs() < 1e-6);
+        assert_eq!(follower.mode(), DetectorMode::Rms);
+
+        let kick = EnvelopeFollower::kick(1000.0);
+        assert!((kick.attack_coeff - (1.0 - (-2.2_f32).exp())).abs() < 1e-6);
+        assert!((kick.release_coeff - (1.0 - (-2.2_f32 / 60.0).exp())).abs() < 1e-6);
+        assert_eq!(kick.mode(), DetectorMode::Peak);
+
+        let sustain = EnvelopeFollower::sustain(1000.0);
+        assert!((sustain.attack_coeff - (1.0 - (-2.2_f32 / 20.0).exp())).abs() < 1e-6);
+        assert!((sustain.release_coeff - (1.0 - (-2.2_f32 / 300.0).exp())).abs() < 1e-6);
+        assert_eq!(sustain.mode(), DetectorMode::Rms);
+    }
+
+    #[test]
+    fn test_envelope_follower_rms_of_sine() {
+        let mut follower = EnvelopeFollower::builder()
+            .attack_ms(50.0)
+            .release_ms(50.0)
+            .mode(DetectorMode::Rms)
+            .sample_rate(44100.0)
+            .build();
+        for i in 0..44100 {
+            follower.next((2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin());
+        }
+        assert!((follower.value() - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.02);
     }
 
     #[test]
@@ -77,4 +527,78 @@ mod tests {
         apply_gain_curve(&mut samples, &curve);
         assert_eq!(samples, vec![0.0, 0.5, 0.5, 1.0]);
     }
+
+    #[test]
+    fn test_envelope_follower_6db_times() {
+        // 5 ms at 44.1 kHz is 220.5 samples, check either side of it
+        let mut follower = EnvelopeFollower::new(0.0, 0.0, 44100.0);
+        follower.set_6db_times(5.0, 20.0, 44100.0);
+        let attack: Vec<f32> = (0..221).map(|_| follower.next(1.0)).collect();
+        assert!(attack[219] < 0.5 && attack[220] >= 0.5);
+        assert!((attack[220] - 0.5).abs() < 0.01);
+
+        // Release from full scale to -6 dB in 20 ms, 882 samples
+        follower = EnvelopeFollower::new(0.0, 0.0, 44100.0);
+        follower.next(1.0);
+        follower.set_6db_times(5.0, 20.0, 44100.0);
+        let release: Vec<f32> = (0..882).map(|_| follower.next(0.0)).collect();
+        assert!((nih_plug::util::gain_to_db(release[881]) + 6.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_envelope_follower_log_settles_uniformly() {
+        // Time for the envelope to settle within 3 dB of the new level after dropping by `drop_db`
+        let settle_time = |mode: DetectorMode, drop_db: f32| {
+            let mut follower = EnvelopeFollower::builder()
+                .attack_ms(0.0)
+                .release_ms(10.0)
+                .mode(mode)
+                .sample_rate(1000.0)
+                .build();
+            follower.next(1.0);
+            let target = 10.0_f32.powf(-drop_db / 20.0);
+            (1..).find(|_| level_to_db(follower.next(target)) < -drop_db + 3.0).unwrap()
+        };
+
+        // The linear follower crawls through the last few dB of a big drop, in the log domain
+        // the settle time barely depends on the size of the drop
+        let ratio = |mode| settle_time(mode, 60.0) as f32 / settle_time(mode, 20.0) as f32;
+        assert!(ratio(DetectorMode::Peak) > 2.0);
+        assert!(ratio(DetectorMode::Log) < 1.7);
+
+        let mut follower = EnvelopeFollower::builder().mode(DetectorMode::Log).build();
+        assert_eq!(follower.value(), 0.0);
+        follower.next(0.5);
+        follower.reset();
+        assert_eq!(follower.next(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_envelope_follower_release_curves() {
+        let release = |curve: ReleaseCurve| {
+            let mut follower = EnvelopeFollower::new(0.0, 100.0, 1000.0);
+            follower.set_release_curve(curve, 100.0, 1000.0);
+            follower.next(1.0);
+            (0..100).map(|_| follower.next(0.0)).collect::<Vec<f32>>()
+        };
+        let exponential = release(ReleaseCurve::Exponential);
+        let s_curve = release(ReleaseCurve::SCurve);
+        let linear = release(ReleaseCurve::Linear);
+
+        // The S-curve eases out of the peak and into silence
+        assert!(1.0 - s_curve[9] < 0.5 * (1.0 - exponential[9]));
+        assert!(s_curve[94] - s_curve[99] < exponential[94] - exponential[99]);
+        assert_eq!(s_curve[99], 0.0);
+        assert!((linear[49] - 0.5).abs() < 1e-5);
+        assert_eq!(linear[99], 0.0);
+
+        // A held input stops the release, which restarts from there once the input drops
+        let mut follower = EnvelopeFollower::new(0.0, 100.0, 1000.0);
+        follower.set_release_curve(ReleaseCurve::Linear, 100.0, 1000.0);
+        follower.next(1.0);
+        let held: Vec<f32> = (0..200).map(|_| follower.next(0.5)).collect();
+        assert!((held[9] - 0.9).abs() < 1e-5);
+        assert!(held[50..].iter().all(|&s| s == 0.5));
+        assert!((follower.next(0.0) - 0.495).abs() < 1e-5);
+    }
 }
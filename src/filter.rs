@@ -0,0 +1,545 @@
+use crate::envelope::flush_denormal;
+
+/// Q of a second order Butterworth section.
+pub const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Second order IIR filter in transposed direct form II, coefficients follow the RBJ audio EQ
+/// cookbook.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Default for Biquad {
+    /// A pass-through filter.
+    fn default() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+}
+
+impl Biquad {
+    pub fn lowpass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_lowpass(freq, q, sample_rate);
+        filter
+    }
+
+    pub fn highpass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_highpass(freq, q, sample_rate);
+        filter
+    }
+
+    pub fn allpass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_allpass(freq, q, sample_rate);
+        filter
+    }
+
+    /// Band-pass with a peak gain of one at `freq`.
+    pub fn bandpass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_bandpass(freq, q, sample_rate);
+        filter
+    }
+
+    /// RBJ high shelf with a shelf slope of one, `gain_db` above `freq`.
+    pub fn high_shelf(freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_high_shelf(freq, gain_db, sample_rate);
+        filter
+    }
+
+    /// Update the coefficients while keeping the filter state.
+    pub fn set_lowpass(&mut self, freq: f32, q: f32, sample_rate: f32) {
+        let (cos, alpha) = Self::prewarp(freq, q, sample_rate);
+        self.set_coefficients(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        );
+    }
+
+    /// Update the coefficients while keeping the filter state.
+    pub fn set_highpass(&mut self, freq: f32, q: f32, sample_rate: f32) {
+        let (cos, alpha) = Self::prewarp(freq, q, sample_rate);
+        self.set_coefficients(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        );
+    }
+
+    /// Update the coefficients while keeping the filter state.
+    pub fn set_allpass(&mut self, freq: f32, q: f32, sample_rate: f32) {
+        let (cos, alpha) = Self::prewarp(freq, q, sample_rate);
+        self.set_coefficients(
+            1.0 - alpha,
+            -2.0 * cos,
+            1.0 + alpha,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        );
+    }
+
+    /// Update the coefficients while keeping the filter state.
+    pub fn set_bandpass(&mut self, freq: f32, q: f32, sample_rate: f32) {
+        let (cos, alpha) = Self::prewarp(freq, q, sample_rate);
+        self.set_coefficients(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha);
+    }
+
+    /// Update the coefficients while keeping the filter state. Negating `gain_db` gives the
+    /// exact inverse filter.
+    pub fn set_high_shelf(&mut self, freq: f32, gain_db: f32, sample_rate: f32) {
+        let a = 10.0_f32.powf(gain_db / 40.0);
+        // A shelf slope of one is the same as a Butterworth Q
+        let (cos, alpha) = Self::prewarp(freq, BUTTERWORTH_Q, sample_rate);
+        let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        self.set_coefficients(
+            a * ((a + 1.0) + (a - 1.0) * cos + two_sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - two_sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos + two_sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - two_sqrt_a_alpha,
+        );
+    }
+
+    fn prewarp(freq: f32, q: f32, sample_rate: f32) -> (f32, f32) {
+        let freq = freq.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * std::f32::consts::PI * freq / sample_rate;
+        (w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    fn set_coefficients(&mut self, b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) {
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
+    }
+
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let out = self.b0 * sample + self.z1;
+        self.z1 = flush_denormal(self.b1 * sample - self.a1 * out + self.z2);
+        self.z2 = flush_denormal(self.b2 * sample - self.a2 * out);
+        out
+    }
+}
+
+/// Fourth order Linkwitz-Riley crossover. The two bands sum back to an all-pass response, so
+/// processing them separately doesn't leave a hole at the crossover frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crossover {
+    lowpass: [Biquad; 2],
+    highpass: [Biquad; 2],
+}
+
+impl Crossover {
+    pub fn new(freq: f32, sample_rate: f32) -> Self {
+        Self {
+            lowpass: [Biquad::lowpass(freq, BUTTERWORTH_Q, sample_rate); 2],
+            highpass: [Biquad::highpass(freq, BUTTERWORTH_Q, sample_rate); 2],
+        }
+    }
+
+    /// Update the crossover frequency while keeping the filter state.
+    pub fn set_frequency(&mut self, freq: f32, sample_rate: f32) {
+        for filter in &mut self.lowpass {
+            filter.set_lowpass(freq, BUTTERWORTH_Q, sample_rate);
+        }
+        for filter in &mut self.highpass {
+            filter.set_highpass(freq, BUTTERWORTH_Q, sample_rate);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.lowpass.iter_mut().chain(self.highpass.iter_mut()) {
+            filter.reset();
+        }
+    }
+
+    /// Split a sample into its `(low, high)` bands.
+    pub fn split(&mut self, sample: f32) -> (f32, f32) {
+        let low = self.lowpass.iter_mut().fold(sample, |s, f| f.process(s));
+        let high = self.highpass.iter_mut().fold(sample, |s, f| f.process(s));
+        (low, high)
+    }
+}
+
+/// Butterworth highpass and lowpass in series to limit what a detector listens to. Either side
+/// of the band can be left open.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct KeyFilter {
+    highpass: Option<Biquad>,
+    lowpass: Option<Biquad>,
+}
+
+impl KeyFilter {
+    /// Update the band while keeping the filter state, `None` leaves that side open.
+    pub fn set_band(&mut self, low_hz: Option<f32>, high_hz: Option<f32>, sample_rate: f32) {
+        self.highpass = low_hz.map(|freq| {
+            let mut filter = self.highpass.unwrap_or_default();
+            filter.set_highpass(freq, BUTTERWORTH_Q, sample_rate);
+            filter
+        });
+        self.lowpass = high_hz.map(|freq| {
+            let mut filter = self.lowpass.unwrap_or_default();
+            filter.set_lowpass(freq, BUTTERWORTH_Q, sample_rate);
+            filter
+        });
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.highpass.iter_mut().chain(self.lowpass.iter_mut()) {
+            filter.reset();
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.highpass
+            .iter_mut()
+            .chain(self.lowpass.iter_mut())
+            .fold(sample, |s, f| f.process(s))
+    }
+}
+
+/// Cutoff of the output's DC blocker, well below anything a sub should reach.
+pub const DC_BLOCK_HZ: f32 = 5.0;
+
+/// One pole, one zero highpass that removes DC while leaving everything but the lowest few Hz
+/// alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DcBlocker {
+    pole: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl DcBlocker {
+    pub fn new(freq: f32, sample_rate: f32) -> Self {
+        let mut blocker = Self {
+            pole: 0.0,
+            x1: 0.0,
+            y1: 0.0,
+        };
+        blocker.set_frequency(freq, sample_rate);
+        blocker
+    }
+
+    /// Update the cutoff while keeping the filter state.
+    pub fn set_frequency(&mut self, freq: f32, sample_rate: f32) {
+        self.pole = (1.0 - 2.0 * std::f32::consts::PI * freq / sample_rate).clamp(0.0, 1.0);
+    }
+
+    pub fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.y1 = 0.0;
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let out = flush_denormal(sample - self.x1 + self.pole * self.y1);
+        self.x1 = sample;
+        self.y1 = out;
+        out
+    }
+}
+
+/// Largest feedback magnitude the comb filter uses, anything past it is clamped.
+pub const MAX_COMB_FEEDBACK: f32 = 0.999;
+/// Output level the comb filter treats as a runaway once it's held for `COMB_RUNAWAY_SAMPLES`.
+pub const COMB_RUNAWAY_LEVEL: f32 = 4.0;
+/// Consecutive samples above `COMB_RUNAWAY_LEVEL` before the comb filter mutes itself.
+pub const COMB_RUNAWAY_SAMPLES: u32 = 64;
+/// Samples the comb filter takes to fade back in after a safety mute.
+pub const COMB_MUTE_RECOVERY_SAMPLES: u32 = 512;
+
+/// Feedback comb filter with a fractional delay. The peaks sit at multiples of `1 / delay` with
+/// notches halfway between them, the output is scaled so the peaks stay at unity gain.
+///
+/// If the output stays far above full scale anyway, the delay line is cleared and the output
+/// fades back in from silence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombFilter {
+    line: Vec<f32>,
+    pos: usize,
+    /// Consecutive samples the output has been above `COMB_RUNAWAY_LEVEL`.
+    over: u32,
+    mute_gain: f32,
+    mutes: u32,
+}
+
+impl CombFilter {
+    pub fn new(max_delay_samples: usize) -> Self {
+        Self {
+            line: vec![0.0; max_delay_samples + 2],
+            pos: 0,
+            over: 0,
+            mute_gain: 1.0,
+            mutes: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.line.fill(0.0);
+        self.pos = 0;
+        self.over = 0;
+        self.mute_gain = 1.0;
+        self.mutes = 0;
+    }
+
+    /// How many times the safety mute has kicked in since the last reset.
+    pub fn mute_count(&self) -> u32 {
+        self.mutes
+    }
+
+    /// Filter one sample with a delay of `delay` samples, clamped to the maximum the filter was
+    /// created with. `feedback` is clamped to `MAX_COMB_FEEDBACK` in magnitude.
+    pub fn process(&mut self, sample: f32, delay: f32, feedback: f32) -> f32 {
+        let feedback = feedback.clamp(-MAX_COMB_FEEDBACK, MAX_COMB_FEEDBACK);
+        let len = self.line.len();
+        let delay = delay.clamp(1.0, (len - 2) as f32);
+        let whole = delay.floor() as usize;
+        let fraction = delay - whole as f32;
+        let newer = self.line[(self.pos + len - whole) % len];
+        let older = self.line[(self.pos + len - whole - 1) % len];
+        let delayed = newer + fraction * (older - newer);
+
+        let out = flush_denormal(sample + feedback * delayed);
+        self.line[self.pos] = out;
+        self.pos = (self.pos + 1) % len;
+        let out = out * (1.0 - feedback.abs());
+
+        if out.abs() > COMB_RUNAWAY_LEVEL {
+            self.over += 1;
+        } else {
+            self.over = 0;
+        }
+        if self.over >= COMB_RUNAWAY_SAMPLES {
+            self.line.fill(0.0);
+            self.over = 0;
+            self.mute_gain = 0.0;
+            self.mutes += 1;
+        }
+        let out = out * self.mute_gain;
+        self.mute_gain = (self.mute_gain + 1.0 / COMB_MUTE_RECOVERY_SAMPLES as f32).min(1.0);
+        out
+    }
+}
+
+/// Plain delay by a whole number of samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelayLine {
+    line: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    pub fn new(max_delay_samples: usize) -> Self {
+        Self {
+            line: vec![0.0; max_delay_samples + 1],
+            pos: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.line.fill(0.0);
+        self.pos = 0;
+    }
+
+    /// Write `sample` and read back the one from `delay` samples ago, clamped to the maximum the
+    /// line was created with.
+    pub fn process(&mut self, sample: f32, delay: usize) -> f32 {
+        let len = self.line.len();
+        self.line[self.pos] = sample;
+        let out = self.line[(self.pos + len - delay.min(len - 1)) % len];
+        self.pos = (self.pos + 1) % len;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::magnitude_at;
+
+    fn sine(freq: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_biquad_lowpass_attenuates_highs() {
+        let sample_rate = 44100.0;
+        let mut low = Biquad::lowpass(200.0, BUTTERWORTH_Q, sample_rate);
+        let mut high = Biquad::lowpass(200.0, BUTTERWORTH_Q, sample_rate);
+        let low_out: Vec<f32> = sine(50.0, sample_rate, 4410)
+            .into_iter()
+            .map(|s| low.process(s))
+            .collect();
+        let high_out: Vec<f32> = sine(5000.0, sample_rate, 4410)
+            .into_iter()
+            .map(|s| high.process(s))
+            .collect();
+        assert!(magnitude_at(&low_out, sample_rate, 50.0) > 0.9);
+        assert!(magnitude_at(&high_out, sample_rate, 5000.0) < 0.01);
+    }
+
+    #[test]
+    fn test_biquad_allpass_keeps_magnitude() {
+        let sample_rate = 44100.0;
+        for freq in [50.0, 500.0, 5000.0] {
+            let mut filter = Biquad::allpass(500.0, BUTTERWORTH_Q, sample_rate);
+            let out: Vec<f32> = sine(freq, sample_rate, 8820)
+                .into_iter()
+                .map(|s| filter.process(s))
+                .collect();
+            assert!((magnitude_at(&out[4410..], sample_rate, freq) - 1.0).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_biquad_bandpass_peaks_at_center() {
+        let sample_rate = 44100.0;
+        let mut gains = [50.0, 1000.0, 10000.0].map(|freq| {
+            let mut filter = Biquad::bandpass(1000.0, 4.0, sample_rate);
+            let out: Vec<f32> = sine(freq, sample_rate, 8820)
+                .into_iter()
+                .map(|s| filter.process(s))
+                .collect();
+            magnitude_at(&out[4410..], sample_rate, freq)
+        });
+        assert!((gains[1] - 1.0).abs() < 0.02);
+        gains[1] = 0.0;
+        assert!(gains.iter().all(|&gain| gain < 0.1));
+    }
+
+    #[test]
+    fn test_key_filter_band() {
+        let sample_rate = 44100.0;
+        let gain = |filter: &mut KeyFilter, freq: f32| {
+            let out: Vec<f32> = sine(freq, sample_rate, 8820)
+                .into_iter()
+                .map(|s| filter.process(s))
+                .collect();
+            magnitude_at(&out[4410..], sample_rate, freq)
+        };
+        let mut open = KeyFilter::default();
+        assert_eq!(open.process(0.25), 0.25);
+        let mut band = KeyFilter::default();
+        band.set_band(Some(100.0), Some(1000.0), sample_rate);
+        assert!(gain(&mut band, 20.0) < 0.05);
+        assert!(gain(&mut band, 300.0) > 0.85);
+        assert!(gain(&mut band, 10000.0) < 0.02);
+    }
+
+    #[test]
+    fn test_crossover_bands_sum_to_unity() {
+        // At the crossover frequency both bands are -6 dB and in phase, so they sum back up
+        let sample_rate = 44100.0;
+        let mut crossover = Crossover::new(500.0, sample_rate);
+        let (low, high): (Vec<f32>, Vec<f32>) = sine(500.0, sample_rate, 8820)
+            .into_iter()
+            .map(|s| crossover.split(s))
+            .unzip();
+        let sum: Vec<f32> = low.iter().zip(&high).map(|(l, h)| l + h).collect();
+        assert!((magnitude_at(&low[4410..], sample_rate, 500.0) - 0.5).abs() < 0.02);
+        assert!((magnitude_at(&sum[4410..], sample_rate, 500.0) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_dc_blocker_removes_offset() {
+        let sample_rate = 44100.0;
+        let mut blocker = DcBlocker::new(DC_BLOCK_HZ, sample_rate);
+        let output: Vec<f32> = (0..44100)
+            .map(|i| {
+                let sine = (2.0 * std::f32::consts::PI * 100.0 * i as f32 / sample_rate).sin();
+                blocker.process(0.5 + sine)
+            })
+            .collect();
+        let tail = &output[22050..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        assert!(mean.abs() < 1e-3);
+        // 100 Hz passes almost untouched
+        let magnitude = magnitude_at(tail, sample_rate, 100.0);
+        assert!((magnitude - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_comb_filter_notches_and_stability() {
+        // A 10 ms delay puts peaks on multiples of 100 Hz and notches halfway between them
+        let sample_rate = 44100.0;
+        let mut comb = CombFilter::new(882);
+        let impulse_response: Vec<f32> = (0..44100)
+            .map(|i| comb.process(if i == 0 { 1.0 } else { 0.0 }, 441.0, 0.5))
+            .collect();
+        for k in 1..5 {
+            let peak = magnitude_at(&impulse_response, sample_rate, k as f32 * 100.0);
+            let notch = magnitude_at(&impulse_response, sample_rate, k as f32 * 100.0 + 50.0);
+            assert!(peak > 2.5 * notch);
+        }
+
+        // Even close to one the feedback dies away
+        let mut comb = CombFilter::new(882);
+        let ringing: Vec<f32> = (0..441000)
+            .map(|i| comb.process(if i == 0 { 1.0 } else { 0.0 }, 100.5, 0.99))
+            .collect();
+        assert!(ringing.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+        assert!(ringing[430000..].iter().all(|s| s.abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_comb_filter_safety_mute() {
+        // An impulse rings on at 0.999 but stays bounded, and feedback past one is clamped
+        for feedback in [0.999, 1.5, -2.0] {
+            let mut comb = CombFilter::new(882);
+            let ringing: Vec<f32> = (0..88200)
+                .map(|i| comb.process(if i == 0 { 1.0 } else { 0.0 }, 441.0, feedback))
+                .collect();
+            assert!(ringing.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+            assert_eq!(comb.mute_count(), 0);
+        }
+
+        // A loud input on one of the peaks builds up past the runaway level and gets muted
+        let mut comb = CombFilter::new(882);
+        let output: Vec<f32> = (0..44100)
+            .map(|_| comb.process(8.0, 10.0, 0.999))
+            .collect();
+        assert!(comb.mute_count() > 0);
+        assert!(output.iter().all(|s| s.is_finite() && s.abs() <= 8.0));
+        let first_mute = output
+            .iter()
+            .position(|&s| s == 0.0)
+            .expect("the mute never engaged");
+        assert!(output[first_mute - 1] > COMB_RUNAWAY_LEVEL);
+        comb.reset();
+        assert_eq!(comb.mute_count(), 0);
+    }
+}
@@ -0,0 +1,82 @@
+/// State of a hard gate with hysteresis. The gate opens once the level rises above the open
+/// threshold and only closes again after it falls below the (lower) close threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GateState {
+    #[default]
+    Closed,
+    Open,
+}
+
+impl GateState {
+    /// Advance the state machine by one sample and return whether the gate is open.
+    pub fn update(&mut self, level: f32, open_threshold: f32, close_threshold: f32) -> bool {
+        *self = match *self {
+            GateState::Closed if level >= open_threshold => GateState::Open,
+            GateState::Open if level < close_threshold => GateState::Closed,
+            state => state,
+        };
+        *self == GateState::Open
+    }
+}
+
+/// Soft knee alternative to the hard gate. The gain fades from 0 to 1 (with a smoothstep in the
+/// dB domain) across `knee_db` centered on `threshold`, both `level` and `threshold` are linear.
+pub fn soft_gate_gain(level: f32, threshold: f32, knee_db: f32) -> f32 {
+    if level <= 0.0 {
+        return 0.0;
+    }
+    if knee_db <= 0.0 {
+        return if level >= threshold { 1.0 } else { 0.0 };
+    }
+
+    let over_db = 20.0 * (level / threshold.max(f32::MIN_POSITIVE)).log10();
+    let t = (over_db / knee_db + 0.5).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gate_opens_and_closes() {
+        let mut gate = GateState::default();
+        assert!(!gate.update(0.05, 0.1, 0.01));
+        assert!(gate.update(0.2, 0.1, 0.01));
+        assert!(gate.update(0.05, 0.1, 0.01));
+        assert!(!gate.update(0.005, 0.1, 0.01));
+    }
+
+    #[test]
+    fn test_gate_hysteresis_does_not_chatter() {
+        // Oscillating between the two thresholds should never change the state
+        let levels = [0.02_f32, 0.08].repeat(50);
+
+        let mut gate = GateState::Closed;
+        for &level in &levels {
+            assert!(!gate.update(level, 0.1, 0.01));
+        }
+
+        let mut gate = GateState::Open;
+        for &level in &levels {
+            assert!(gate.update(level, 0.1, 0.01));
+        }
+    }
+
+    #[test]
+    fn test_soft_gate_gain_is_smooth() {
+        let threshold = 0.01;
+        // Sweep from 10 dB below to 10 dB above the threshold in 0.1 dB steps
+        let gains: Vec<f32> = (-100..=100)
+            .map(|i| soft_gate_gain(threshold * 10.0_f32.powf(i as f32 / 200.0), threshold, 12.0))
+            .collect();
+        assert_eq!(gains[0], 0.0);
+        assert_eq!(gains[200], 1.0);
+        assert!((gains[100] - 0.5).abs() < 1e-3);
+        assert!(gains.windows(2).all(|w| w[1] >= w[0] && w[1] - w[0] < 0.02));
+
+        // Without a knee it's the hard gate again
+        assert_eq!(soft_gate_gain(threshold * 0.99, threshold, 0.0), 0.0);
+        assert_eq!(soft_gate_gain(threshold, threshold, 0.0), 1.0);
+    }
+}
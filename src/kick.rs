@@ -0,0 +1,169 @@
+use crate::adsr::Adsr;
+use crate::envelope::EnvelopeFollower;
+use crate::wave::Oscillator;
+
+/// A transient is a fast envelope at least this many times above the slow one.
+const TRANSIENT_RATIO: f32 = 2.0;
+/// The detector re-arms once the fast envelope falls back below this ratio, or below the
+/// threshold.
+const REARM_RATIO: f32 = 1.25;
+/// Quieter onsets than this are ignored.
+const TRANSIENT_THRESHOLD: f32 = 0.01;
+/// Minimum time between two transients.
+const HOLD_OFF_MS: f32 = 50.0;
+/// How far above the target pitch a kick starts its sweep.
+const KICK_SWEEP_OCTAVES: f32 = 2.0;
+
+/// Finds onsets by comparing a fast and a slow peak envelope, the way
+/// `envelope_follower_adaptive` does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransientDetector {
+    fast: EnvelopeFollower,
+    slow: EnvelopeFollower,
+    armed: bool,
+    hold_off: usize,
+    since_last: usize,
+}
+
+impl TransientDetector {
+    pub fn new(sample_rate: f32) -> Self {
+        let hold_off = (HOLD_OFF_MS * 0.001 * sample_rate) as usize;
+        Self {
+            fast: EnvelopeFollower::new(0.1, 30.0, sample_rate),
+            slow: EnvelopeFollower::new(30.0, 30.0, sample_rate),
+            armed: true,
+            hold_off,
+            since_last: hold_off,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+        self.armed = true;
+        self.since_last = self.hold_off;
+    }
+
+    /// Returns whether a transient starts at this sample.
+    pub fn next(&mut self, sample: f32) -> bool {
+        let fast = self.fast.next(sample);
+        let slow = self.slow.next(sample);
+        self.since_last = self.since_last.saturating_add(1);
+        if !self.armed {
+            self.armed = fast < slow * REARM_RATIO || fast < TRANSIENT_THRESHOLD;
+            return false;
+        }
+
+        let onset = fast > TRANSIENT_THRESHOLD
+            && fast > slow * TRANSIENT_RATIO
+            && self.since_last > self.hold_off;
+        if onset {
+            self.armed = false;
+            self.since_last = 0;
+        }
+        onset
+    }
+}
+
+/// Indices of the samples where a transient starts.
+pub fn detect_transients(samples: &[f32], sample_rate: f32) -> Vec<usize> {
+    let mut detector = TransientDetector::new(sample_rate);
+    samples
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &sample)| detector.next(sample).then_some(i))
+        .collect()
+}
+
+/// A sine that sweeps down to its pitch and decays after every trigger, for tuned kick subs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KickVoice {
+    adsr: Adsr,
+    osc: Oscillator,
+    elapsed_ms: f32,
+}
+
+impl Default for KickVoice {
+    fn default() -> Self {
+        Self {
+            adsr: Adsr::new(0.0, 0.0, 0.0, 0.0),
+            osc: Oscillator::default(),
+            // Never triggered, so no retrigger interval holds off the first kick
+            elapsed_ms: f32::INFINITY,
+        }
+    }
+}
+
+impl KickVoice {
+    /// Restart the kick from a zero crossing. `amp_decay_ms` is how long it takes to fade out.
+    pub fn trigger(&mut self, amp_decay_ms: f32) {
+        self.adsr = Adsr::new(0.0, amp_decay_ms, 0.0, 0.0);
+        self.adsr.note_on();
+        self.osc = Oscillator::default();
+        self.elapsed_ms = 0.0;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The current amplitude envelope.
+    pub fn level(&self) -> f32 {
+        self.adsr.level()
+    }
+
+    /// Time since the last trigger, infinite if the kick was never triggered.
+    pub fn elapsed_ms(&self) -> f32 {
+        self.elapsed_ms
+    }
+
+    /// Next sample of the kick. The pitch starts `KICK_SWEEP_OCTAVES` above `freq` and falls
+    /// towards it exponentially with a time constant of `pitch_decay_ms`.
+    pub fn next(&mut self, freq: f32, pitch_decay_ms: f32, sample_rate: f32) -> f32 {
+        let sweep = if pitch_decay_ms > 0.0 {
+            KICK_SWEEP_OCTAVES * (-self.elapsed_ms / pitch_decay_ms).exp()
+        } else {
+            0.0
+        };
+        self.elapsed_ms += 1000.0 / sample_rate;
+        let level = self.adsr.next(sample_rate);
+        level * self.osc.next(freq * 2.0_f32.powf(sweep), sample_rate).sin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_transients_finds_onsets() {
+        // Two short 1 kHz bursts with silence around them
+        let sample_rate = 44100.0;
+        let input: Vec<f32> = (0..44100)
+            .map(|i| {
+                let burst = (4410..4631).contains(&i) || (26460..26681).contains(&i);
+                let tone = (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate).sin();
+                if burst {
+                    tone
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let onsets = detect_transients(&input, sample_rate);
+        assert_eq!(onsets.len(), 2);
+        assert!((4410..4430).contains(&onsets[0]));
+        assert!((26460..26480).contains(&onsets[1]));
+        assert!(detect_transients(&[0.5; 4410], sample_rate).len() <= 1);
+    }
+
+    #[test]
+    fn test_kick_voice_decays() {
+        let mut kick = KickVoice::default();
+        assert_eq!(kick.next(60.0, 10.0, 1000.0), 0.0);
+        kick.trigger(100.0);
+        let burst: Vec<f32> = (0..200).map(|_| kick.next(60.0, 10.0, 1000.0)).collect();
+        assert!(burst[..50].iter().any(|s| s.abs() > 0.3));
+        assert!(burst[110..].iter().all(|&s| s == 0.0));
+    }
+}
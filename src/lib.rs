@@ -1,260 +1,4530 @@
+use atomic_float::AtomicF32;
 use nih_plug::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 pub mod wave;
-pub use wave::{saw_wave, saw_with_gain, sine_wave, sine_with_gain};
+pub use wave::{
+    additive_wave, additive_wave_stretch, max_safe_terms, octave_divider, saw_ramp, saw_wave,
+    saw_wave_polyblep, saw_wave_slope, saw_with_gain, sine_wave, sine_with_gain, Oscillator,
+    Wavetable,
+};
+pub mod adsr;
+pub use adsr::Adsr;
+pub mod compressor;
+pub use compressor::Compressor;
 pub mod envelope;
-pub use envelope::{apply_gain_curve, envelope_follower};
+pub use envelope::{
+    apply_gain_curve, envelope_follower, envelope_follower_adaptive, envelope_follower_samples,
+    six_db_coeff, smoothing_coeff, DetectorMode, EnvelopeFollower, EnvelopeFollowerBuilder,
+    ReleaseCurve,
+};
+pub mod gate;
+pub use gate::{soft_gate_gain, GateState};
+pub mod filter;
+pub use filter::{Biquad, CombFilter, Crossover, DcBlocker, DelayLine, KeyFilter};
+pub mod kick;
+pub use kick::{detect_transients, KickVoice, TransientDetector};
+pub mod limiter;
+pub use limiter::Limiter;
+pub mod meter;
+pub use meter::{normalize_peak, CorrelationMeter, TruePeakMeter};
+pub mod modulation;
+pub use modulation::{bar_length_secs, ModDestination, ModMatrix, ModRoute, ModSource};
+#[cfg(feature = "analysis")]
+pub mod analysis;
+#[cfg(feature = "analysis")]
+pub use analysis::spectral_centroid;
+pub mod pitch;
+pub mod rng;
+pub mod shaper;
+pub mod voice;
+pub use voice::{SawStack, VoicePool};
+pub use rng::{SmoothNoise, XorShift32};
+pub use pitch::{
+    cents_to_ratio, detect_pitch, quantize_pitch, Glide, GlideCurve, PhaseDetector, PitchTracker,
+    Scale,
+};
+#[cfg(test)]
+mod test_util;
 
 // This is a shortened version of the gain example with most comments removed, check out
 // https://github.com/robbert-vdh/nih-plug/blob/master/plugins/examples/gain/src/lib.rs to get
 // started
 
-struct SubrouRs {
+pub struct SubrouRs {
     params: Arc<SubrouRsParams>,
     sample_rate: f32,
+    /// Envelope follower per channel, only the first one is used while the channels are linked.
+    followers: Vec<EnvelopeFollower>,
+    /// Splits off the input's high band for the crossfeed.
+    crossfeed_hpf: Biquad,
+    /// Fast envelope on the crossfeed's high band.
+    crossfeed_follower: EnvelopeFollower,
+    /// RMS levels of the input and the generated sub for the auto level.
+    auto_level_input: EnvelopeFollower,
+    auto_level_sub: EnvelopeFollower,
+    /// RMS levels of the unprocessed and the processed output for `bypass_match`.
+    bypass_match_dry: EnvelopeFollower,
+    bypass_match_wet: EnvelopeFollower,
+    /// Noise gate per envelope, only the first one is used while the channels are linked.
+    gates: Vec<GateState>,
+    /// State of the `curve_smooth_ms` smoother per envelope.
+    curve_smoothers: Vec<f32>,
+    /// Oscillators for the saw that follows the input.
+    saws: SawStack,
+    /// Low band of the detector signal and its previous sample, for `zero_cross_sync`.
+    sync_lpf: Biquad,
+    sync_last: f32,
+    phase_detector: PhaseDetector,
+    /// Per channel band split used by the mono maker.
+    crossovers: Vec<Crossover>,
+    /// Saws for the held notes in MIDI mode.
+    voices: VoicePool,
+    /// Last MIDI pitch bend, from `-1` for fully down to `1` for fully up.
+    pitch_bend: f32,
+    /// Onset detection on the detector signal and the sine it fires in kick mode.
+    transients: TransientDetector,
+    kick: KickVoice,
+    /// Portamento on the pitch of the input following and kick modes.
+    glide: Glide,
+    /// One DC blocker per output channel.
+    dc_blockers: Vec<DcBlocker>,
+    /// The sub's own envelope for `amp_env`.
+    amp_env: Adsr,
+    /// Whether the gate was open on the last sample, `amp_env` triggers when it opens.
+    amp_env_open: bool,
+    /// Follows the pitch of the input for pitch tracking.
+    pitch_tracker: PitchTracker,
+    /// Last tracked pitch in Hz for displays, `0` when nothing is detected.
+    detected_pitch: Arc<AtomicF32>,
+    /// Phase correlation between the first two output channels, for mono compatibility meters.
+    correlation_meter: CorrelationMeter,
+    output_correlation: Arc<AtomicF32>,
+    /// Latched once an output sample goes past full scale, until `reset_clip`.
+    clipped: Arc<AtomicBool>,
+    /// One true peak meter per output channel.
+    true_peak_meters: Vec<TruePeakMeter>,
+    /// Highest true peak of all channels during the last block.
+    true_peak: Arc<AtomicF32>,
+    /// Gain stage of the sub on the last sample of the last block, `0..1`.
+    current_gain: Arc<AtomicF32>,
+    /// Noise source for the output dither.
+    rng: XorShift32,
+    /// Source of the `drift` detune.
+    drift_noise: SmoothNoise,
+    /// Per harmonic phase offsets for `phase_spread`.
+    harmonic_offsets: [f32; SAW_HARMONICS.len()],
+    /// Seed from `set_seed`, takes precedence over the `seed` parameter.
+    seed_override: Option<u32>,
+    /// Phases of the additive oscillator's partials while they're stretched.
+    partials: [Oscillator; ADDITIVE_SAW.len()],
+    /// Compressor on the output, ahead of the limiter.
+    compressor: Compressor,
+    limiter: Limiter,
+    /// All-pass chain that derives the side signal for `decorrelate` from the generated sub.
+    decorrelator: [Biquad; DECORRELATE_HZ.len()],
+    /// Delays the right channel's sub for `haas_ms`.
+    haas: DelayLine,
+    /// Shelves around the drive stage for `emphasis_hz`.
+    emphasis: shaper::Emphasis,
+    /// Runs the drive stage oversampled for `oversample_shaper`.
+    oversampler: shaper::Oversampler,
+    /// Comb filter on the generated sub for `comb_delay_ms`.
+    comb: CombFilter,
+    /// Band-pass mixed into the generated sub for `formant_hz`.
+    formant: Biquad,
+    /// Shelf on the generated sub for `hi_shelf_gain_db`.
+    hi_shelf: Biquad,
+    /// Lowpass on the generated sub for `cutoff_hz`.
+    sub_lowpass: Biquad,
+    /// Band limits of the detector input, one per channel, for `key_low_hz` and `key_high_hz`.
+    key_filters: Vec<KeyFilter>,
+    /// Modulated cutoff of `sub_lowpass`, updated once per block.
+    filter_cutoff: f32,
+    /// Routes from the envelope and LFO to the generator's parameters.
+    mod_matrix: ModMatrix,
+    /// Channels that feed the linked detector.
+    detector_channels: DetectorChannels,
+    /// Gain curve that replaces the detected envelope when set.
+    custom_curve: Option<Vec<f32>>,
+    /// Position of the next sample in `custom_curve`.
+    custom_curve_pos: usize,
+    /// Trim in dB applied to the sub added to each output channel.
+    channel_trims: [f32; MAX_TRIM_CHANNELS],
+    /// Samples generated since the last reset, for `fade_in_ms`.
+    fade_in_pos: usize,
+    /// How far the output has faded over to the unprocessed signal, `1` is fully bypassed.
+    bypass_mix: f32,
+    /// Mono sum of the aux input for the current block, empty unless `source` is `Aux` and the
+    /// host passed an aux input. Processing the block takes it.
+    aux_mono: Vec<f32>,
+    /// Copy of the unprocessed input for the bypass crossfade, one buffer per channel. Sized
+    /// for the host's maximum block size in `initialize()`.
+    dry_scratch: Vec<Vec<f32>>,
+    /// Latency last reported to the host, in samples.
+    latency: u32,
 }
 
-#[derive(Params)]
-struct SubrouRsParams {
-    /// Post gain applied after the generated saw wave.
-    #[id = "post_gain"]
-    pub post_gain: FloatParam,
+/// Number of output channels with their own sub trim, channels past this get no trim.
+pub const MAX_TRIM_CHANNELS: usize = 8;
 
-    /// Fundamental pitch for the generated saw wave.
-    #[id = "pitch"]
-    pub pitch: FloatParam,
+/// Longest lookahead the limiter can be set to.
+const MAX_LOOKAHEAD_MS: f32 = 20.0;
+/// Release time of the output limiter.
+const LIMITER_RELEASE_MS: f32 = 50.0;
+/// Release time of the limiter without lookahead, short so it doesn't hold the level down after
+/// the peaks it had to catch instantly.
+const LIMITER_ZERO_LATENCY_RELEASE_MS: f32 = 10.0;
+/// Length of the crossfade when the plugin is bypassed or un-bypassed.
+const BYPASS_FADE_MS: f32 = 10.0;
 
-    /// Output channel, `0` for all channels or 1-based channel index.
-    #[id = "out_channel"]
-    pub out_channel: IntParam,
+fn max_lookahead_samples(sample_rate: f32) -> usize {
+    (MAX_LOOKAHEAD_MS * 0.001 * sample_rate).ceil() as usize
 }
 
-impl Default for SubrouRs {
-    fn default() -> Self {
-        Self {
-            params: Arc::new(SubrouRsParams::default()),
-            sample_rate: 44100.0,
+/// The sub's lowpass is left out entirely at or above this cutoff.
+const MAX_CUTOFF_HZ: f32 = 20000.0;
+/// Lowest cutoff of the filter parameters, the key filter's highpass is off here.
+const MIN_CUTOFF_HZ: f32 = 20.0;
+
+fn cutoff_param(name: &str, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min: MIN_CUTOFF_HZ,
+            max: MAX_CUTOFF_HZ,
+            factor: FloatRange::skew_factor(-2.0),
+        },
+    )
+    .with_unit(" Hz")
+    .with_value_to_string(formatters::v2s_f32_rounded(0))
+}
+
+/// Longest delay of the comb filter on the generated sub.
+const MAX_COMB_DELAY_MS: f32 = 20.0;
+
+fn max_comb_delay_samples(sample_rate: f32) -> usize {
+    (MAX_COMB_DELAY_MS * 0.001 * sample_rate).ceil() as usize
+}
+
+/// Longest delay `haas_ms` can be set to, past this the delay turns into an echo.
+const MAX_HAAS_MS: f32 = 30.0;
+
+fn max_haas_samples(sample_rate: f32) -> usize {
+    (MAX_HAAS_MS * 0.001 * sample_rate).ceil() as usize
+}
+
+/// Crossover frequency of the high band used by the crossfeed.
+const CROSSFEED_HZ: f32 = 200.0;
+
+/// Cutoff of the low band whose zero crossings `zero_cross_sync` follows.
+const SYNC_LOWPASS_HZ: f32 = 200.0;
+
+/// Smoothing of the phase difference `phase_align` measures, long enough to average out the
+/// ripple at twice the lowest pitches.
+const PHASE_ALIGN_DETECT_MS: f32 = 20.0;
+
+/// Time `phase_align` takes to pull the saw most of the way onto the input's phase.
+const PHASE_ALIGN_MS: f32 = 50.0;
+
+/// Most the auto level will boost the sub by, so it doesn't blow up quiet subs.
+const MAX_AUTO_LEVEL_GAIN: f32 = 16.0;
+
+/// Sliding RMS window used by the auto level.
+fn auto_level_meter(sample_rate: f32) -> EnvelopeFollower {
+    EnvelopeFollower::builder()
+        .attack_ms(300.0)
+        .release_ms(300.0)
+        .mode(DetectorMode::Rms)
+        .sample_rate(sample_rate)
+        .build()
+}
+
+/// Centre frequencies of the all-pass stages used by `decorrelate`.
+const DECORRELATE_HZ: [f32; 3] = [150.0, 600.0, 2400.0];
+
+fn decorrelator(sample_rate: f32) -> [Biquad; DECORRELATE_HZ.len()] {
+    DECORRELATE_HZ.map(|freq| Biquad::allpass(freq, filter::BUTTERWORTH_Q, sample_rate))
+}
+
+/// Dither amplitude, one least significant bit at 24-bit.
+const DITHER_LSB: f32 = 1.0 / 8_388_608.0;
+
+/// Mono fold down of the input channels, this is what the linked envelope detector follows.
+fn detector_signal(
+    slices: &[&mut [f32]],
+    mode: DetectorMonoMode,
+    channels: &DetectorChannels,
+) -> Vec<f32> {
+    let num_samples = slices.first().map_or(0, |channel| channel.len());
+    let mut mono = vec![0.0f32; num_samples];
+    match mode {
+        DetectorMonoMode::Average | DetectorMonoMode::WeightedSum => {
+            let mut total_weight = 0.0;
+            for (idx, channel) in slices.iter().enumerate() {
+                let weight = channels.weight(idx);
+                total_weight += weight;
+                if weight == 0.0 {
+                    continue;
+                }
+                for (i, &sample) in channel.iter().enumerate() {
+                    mono[i] += weight * sample;
+                }
+            }
+            if mode == DetectorMonoMode::Average && total_weight > 0.0 {
+                for sample in &mut mono {
+                    *sample /= total_weight;
+                }
+            }
+        }
+        DetectorMonoMode::Max => {
+            for (idx, channel) in slices.iter().enumerate() {
+                let weight = channels.weight(idx);
+                for (i, &sample) in channel.iter().enumerate() {
+                    mono[i] = mono[i].max(weight * sample.abs());
+                }
+            }
         }
     }
+    mono
 }
 
-impl Default for SubrouRsParams {
+/// Which input channels feed the linked detector and how much each of them counts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectorChannels {
+    /// Channel `n` contributes if bit `n` is set.
+    pub mask: u32,
+    /// Per channel weights, channels past the end have a weight of 1.
+    pub weights: Vec<f32>,
+}
+
+impl Default for DetectorChannels {
     fn default() -> Self {
         Self {
-            post_gain: FloatParam::new(
-                "Post Gain",
-                1.0,
-                FloatRange::Skewed {
-                    min: 0.0,
-                    max: util::db_to_gain(12.0),
-                    factor: FloatRange::gain_skew_factor(util::MINUS_INFINITY_DB, 12.0),
-                },
-            )
-            .with_smoother(SmoothingStyle::Logarithmic(10.0))
-            .with_unit(" dB")
-            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
-            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
-            pitch: FloatParam::new(
-                "Pitch",
-                440.0,
-                FloatRange::Linear { min: 10.0, max: 2000.0 },
-            ),
-            out_channel: IntParam::new(
-                "Output Channel",
-                0,
-                IntRange::Linear { min: 0, max: 10 },
-            ),
+            mask: u32::MAX,
+            weights: Vec::new(),
         }
     }
 }
 
-impl Plugin for SubrouRs {
-    const NAME: &'static str = "Subrou Rs";
-    const VENDOR: &'static str = "Daishi Suzuki";
-    const URL: &'static str = env!("CARGO_PKG_HOMEPAGE");
-    const EMAIL: &'static str = "zukky.rikugame@gmail.com";
+impl DetectorChannels {
+    pub fn weight(&self, channel: usize) -> f32 {
+        if channel >= 32 || self.mask & (1 << channel) == 0 {
+            0.0
+        } else {
+            self.weights.get(channel).copied().unwrap_or(1.0)
+        }
+    }
+}
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+/// Input the envelope and the pitch tracker listen to.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSource {
+    /// The main input, which the sub is also added to.
+    Main,
+    /// The aux input, so the sub reinforces a different signal than the one it's mixed into.
+    /// Falls back to the main input while nothing is connected.
+    Aux,
+}
 
-    // The first audio IO layout is used as the default. The other layouts may be selected either
-    // explicitly or automatically by the host or the user depending on the plugin API/backend.
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
+/// How the input channels are folded down to mono for the detector.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectorMonoMode {
+    /// Average of all channels. Out of phase material cancels.
+    Average,
+    /// Loudest rectified channel, immune to cancellation but no longer a waveform, so pitch
+    /// tracking works better with the average.
+    Max,
+    /// Sum of the channels scaled by their `DetectorChannels` weights without normalizing, for
+    /// surround layouts where e.g. the LFE should count for more or less than the rest.
+    #[name = "Weighted Sum"]
+    WeightedSum,
+}
 
-        aux_input_ports: &[],
-        aux_output_ports: &[],
+/// Amplitudes of the first harmonics of a saw, the series `saw_wave` sums.
+const SAW_HARMONICS: [f32; 4] = [
+    std::f32::consts::FRAC_2_PI,
+    -std::f32::consts::FRAC_1_PI,
+    std::f32::consts::FRAC_2_PI / 3.0,
+    -std::f32::consts::FRAC_1_PI / 2.0,
+];
 
-        // Individual ports and the layout as a whole can be named here. By default these names
-        // are generated as needed. This layout will be called 'Stereo', while a layout with
-        // only one input and output channel would be called 'Mono'.
-        names: PortNames::const_default(),
-    }];
+/// Amplitudes of the harmonics the `amount` macro fades in, and of the partials `stretch` detunes.
+const ADDITIVE_SAW: [f32; 3] = [SAW_HARMONICS[0], SAW_HARMONICS[1], SAW_HARMONICS[2]];
 
+/// Most harmonics the additive saw sums, so the lowest notes don't cost thousands of terms per
+/// sample. At 44.1 kHz the series is complete down to about 43 Hz.
+const MAX_ADDITIVE_TERMS: usize = 512;
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
-    const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
+/// Levels of `h1` to `h4` that leave the additive saw as the full series. Moving any of them
+/// switches it to just those four harmonics.
+const DEFAULT_HARMONIC_LEVELS: [f32; SAW_HARMONICS.len()] = [1.0, 1.0, 1.0, 0.0];
 
-    const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+/// How often the `drift` noise picks a new detune.
+const DRIFT_RATE_HZ: f32 = 0.5;
 
-    // If the plugin can send or receive SysEx messages, it can define a type to wrap around those
-    // messages here. The type implements the `SysExMessage` trait, which allows conversion to and
-    // from plain byte buffers.
-    type SysExMessage = ();
-    // More advanced plugins can use this to run expensive background tasks. See the field's
-    // documentation for more information. `()` means that the plugin does not have any background
-    // tasks.
-    type BackgroundTask = ();
+/// Generator for `seed`. The seed is scrambled first so neighbouring seeds don't start out with
+/// similar values, zero still gives the default generator.
+fn seeded_rng(seed: u32) -> XorShift32 {
+    XorShift32::new(seed.wrapping_mul(0x9e37_79b9))
+}
 
-    fn params(&self) -> Arc<dyn Params> {
-        self.params.clone()
-    }
+/// Phase offsets that give the four harmonic saw about its lowest crest factor, 1.41 against
+/// 1.81 in phase.
+const LOW_CREST_OFFSETS: [f32; SAW_HARMONICS.len()] = [0.0, -2.27, -1.25, -1.41];
 
-    fn initialize(
-        &mut self,
-        _audio_io_layout: &AudioIOLayout,
-        buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
-    ) -> bool {
-        // Resize buffers and perform other potentially expensive initialization operations here.
-        // The `reset()` function is always called right after this function. You can remove this
-        // function if you do not need it.
-        self.sample_rate = buffer_config.sample_rate as f32;
-        true
-    }
+/// Random offsets `harmonic_offsets` draws before it falls back to `LOW_CREST_OFFSETS`.
+const OFFSET_DRAWS: usize = 32;
 
-    fn reset(&mut self) {
-        // Reset buffers and envelopes here. This can be called from the audio thread and may not
-        // allocate. You can remove this function if you do not need it.
-    }
+/// Peak to RMS ratio of one period of the saw's first harmonics at `offsets`.
+fn harmonic_crest_factor(offsets: &[f32]) -> f32 {
+    let samples: [f32; 256] = std::array::from_fn(|i| {
+        let phase = 2.0 * std::f32::consts::PI * i as f32 / 256.0;
+        additive_wave(phase, &SAW_HARMONICS, offsets)
+    });
+    let peak = samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    peak / rms
+}
 
-    fn process(
-        &mut self,
-        buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
-    ) -> ProcessStatus {
-        let num_samples = buffer.samples();
-        if num_samples == 0 {
-            return ProcessStatus::Normal;
+/// Random phase offsets for the first harmonics of the additive saw, scaled by `phase_spread`.
+/// Fully random phases are peakier than the plain saw more often than not, so only draws that
+/// take at least a tenth off its crest factor are kept.
+fn harmonic_offsets(rng: &mut XorShift32) -> [f32; SAW_HARMONICS.len()] {
+    let plain = harmonic_crest_factor(&[]);
+    for _ in 0..OFFSET_DRAWS {
+        let offsets =
+            [(); SAW_HARMONICS.len()].map(|_| rng.next_f32() * 2.0 * std::f32::consts::PI);
+        if harmonic_crest_factor(&offsets) < 0.9 * plain {
+            return offsets;
         }
+    }
+    LOW_CREST_OFFSETS
+}
 
-        let slices = buffer.as_slice();
-        let num_channels = slices.len().max(1);
+/// Oscillator used to generate the saw.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OscQuality {
+    /// Additive saw with every harmonic below Nyquist. Free of aliasing, but the cost grows as the
+    /// pitch drops.
+    Additive,
+    /// PolyBLEP saw with the full harmonic series and reduced aliasing.
+    #[name = "Band Limited"]
+    BandLimited,
+    /// Plain ramp, the cheapest option and fine as long as the pitch stays low.
+    Naive,
+}
 
-        // Sum input to mono
-        let mut mono = vec![0.0f32; num_samples];
-        for channel in slices.iter() {
-            for (i, &sample) in channel.iter().enumerate() {
-                mono[i] += sample;
-            }
-        }
-        for sample in &mut mono {
-            *sample /= num_channels as f32;
-        }
+/// Numeric precision of the generator's phase accumulators.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    /// Single precision, plenty for real time use.
+    Standard,
+    /// Double precision phases so long offline renders don't drift out of tune.
+    #[name = "High Precision"]
+    HighPrecision,
+}
 
-        // Envelope from mono input
-        let curve = envelope_follower(&mono, 10.0, 10.0, self.sample_rate);
+/// Extra drive the `amount` macro adds at its top end.
+const AMOUNT_MAX_DRIVE: f32 = 3.0;
 
-        // Generate saw wave with envelope gain
-        let freq = self.params.pitch.smoothed.next();
-        let post = self.params.post_gain.smoothed.next();
-        let mut saw = Vec::with_capacity(num_samples);
-        for (i, gain) in curve.iter().enumerate() {
-            let phase = 2.0 * std::f32::consts::PI * freq * (i as f32) / self.sample_rate;
-            saw.push(saw_wave(phase, 3) * *gain * post);
-        }
+/// Gain, extra drive and number of additive harmonics for the `amount` macro. The first half
+/// of the range brings in the harmonics and the gain, the second half keeps raising the gain
+/// and adds drive. Half way leaves the sub as the other parameters set it.
+fn amount_macro(amount: f32) -> (f32, f32, usize) {
+    let gain = 2.0 * amount;
+    let drive = (2.0 * amount - 1.0).max(0.0) * AMOUNT_MAX_DRIVE;
+    let terms = 1 + (amount * 2.0 * (ADDITIVE_SAW.len() - 1) as f32).floor() as usize;
+    (gain, drive, terms.min(ADDITIVE_SAW.len()))
+}
 
-        let out_ch = self.params.out_channel.value();
-        if out_ch == 0 {
-            for channel in slices.iter_mut() {
-                for (i, sample) in channel.iter_mut().enumerate() {
-                    *sample += saw[i];
-                }
-            }
-        } else {
-            let idx = (out_ch - 1) as usize;
-            if idx < slices.len() {
-                for (i, sample) in slices[idx].iter_mut().enumerate() {
-                    *sample = saw[i];
-                }
+/// Everything that shapes a single sample of the generated oscillators.
+#[derive(Debug, Clone, Copy)]
+struct OscSettings {
+    quality: OscQuality,
+    /// Phase offsets of the additive harmonics, `None` without `phase_spread`.
+    offsets: Option<[f32; SAW_HARMONICS.len()]>,
+    fifth: f32,
+    octave: f32,
+    /// Most harmonics of the additive saw, from the `amount` macro.
+    max_terms: usize,
+    /// Samples per side the band limited saw corrects its edges over.
+    blep_width: u32,
+    /// Amplitudes of the additive harmonics, `None` while `h1` to `h4` are at their defaults.
+    harmonics: Option<[f32; SAW_HARMONICS.len()]>,
+}
+
+impl OscSettings {
+    fn osc(&self, phase: f32, phase_inc: f32) -> f32 {
+        // Number of harmonics below Nyquist. Once only the fundamental fits every oscillator
+        // degrades to a pure sine, and to silence once not even that fits.
+        // `phase_inc` is the frequency in radians per sample, so the sample rate is a full turn.
+        let mut terms = max_safe_terms(phase_inc, 2.0 * std::f32::consts::PI) as usize;
+        if terms < 2 {
+            return saw_wave(phase, terms as u32);
+        }
+        terms = terms.min(MAX_ADDITIVE_TERMS);
+        // The `amount` macro only thins out the harmonics below its midpoint
+        if self.max_terms < ADDITIVE_SAW.len() {
+            terms = terms.min(self.max_terms);
+        }
+        if let (OscQuality::Additive, Some(harmonics)) = (self.quality, &self.harmonics) {
+            let terms = terms.min(harmonics.len());
+            let offsets = self.offsets.as_ref().map_or(&[][..], |offsets| &offsets[..]);
+            return additive_wave(phase, &harmonics[..terms], offsets);
+        }
+        match (self.quality, &self.offsets) {
+            (OscQuality::Additive, Some(offsets)) => {
+                // Only the first harmonics are spread, the rest of the series stays in phase
+                let spread = terms.min(SAW_HARMONICS.len());
+                additive_wave(phase, &SAW_HARMONICS[..spread], offsets)
+                    + saw_wave(phase, terms as u32)
+                    - saw_wave(phase, spread as u32)
             }
+            (OscQuality::Additive, None) => saw_wave(phase, terms as u32),
+            (OscQuality::BandLimited, _) => saw_wave_polyblep(phase, phase_inc, self.blep_width),
+            // Shifted so it resets at π like the other two
+            (OscQuality::Naive, _) => saw_ramp(phase + std::f32::consts::PI),
         }
+    }
 
-        ProcessStatus::Normal
+    /// The fundamental with the optional fifth and octave stacked on top, `phases` as returned
+    /// by `SawStack::next`.
+    fn render(&self, phases: [f32; 3], phase_inc: f32) -> f32 {
+        let mut sample = self.osc(phases[0], phase_inc);
+        if self.fifth > 0.0 {
+            sample += self.fifth * self.osc(phases[1], phase_inc * 1.5);
+        }
+        if self.octave > 0.0 {
+            sample += self.octave * self.osc(phases[2], phase_inc * 2.0);
+        }
+        sample
     }
 }
 
-impl ClapPlugin for SubrouRs {
-    const CLAP_ID: &'static str = "com.zukky.subrou-rs";
-    const CLAP_DESCRIPTION: Option<&'static str> = Some("SubBaseMaker");
-    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
-    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+#[derive(Params)]
+struct SubrouRsParams {
+    /// Post gain applied after the generated saw wave.
+    #[id = "post_gain"]
+    pub post_gain: FloatParam,
 
-    // Don't forget to change these features
-    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Stereo];
-}
+    /// Gain applied to the passed through input before the generated saw is added.
+    #[id = "dry_gain"]
+    pub dry_gain: FloatParam,
 
-impl Vst3Plugin for SubrouRs {
-    const VST3_CLASS_ID: [u8; 16] = *b"Subrou!!!!!!!!!!";
+    /// One knob macro over the sub's gain, drive and number of harmonics. Half way leaves them
+    /// as set.
+    #[id = "amount"]
+    pub amount: FloatParam,
 
-    // And also don't forget to change these categories
-    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
-        &[Vst3SubCategory::Fx, Vst3SubCategory::Dynamics];
-}
+    /// Fundamental pitch for the generated saw wave. Changes glide over 20 ms so sweeps and
+    /// automation jumps don't zipper.
+    #[id = "pitch"]
+    pub pitch: FloatParam,
 
-nih_export_clap!(SubrouRs);
-nih_export_vst3!(SubrouRs);
+    /// Offset in cents on top of `pitch`, MIDI notes or the tracked pitch, after quantization.
+    #[id = "fine_tune"]
+    pub fine_tune: FloatParam,
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Slow random detune of the generated pitch, up to this many cents either way.
+    #[id = "drift"]
+    pub drift: FloatParam,
 
-    struct DummyContext;
+    /// Upper limit for the generated frequency, whether it comes from `pitch`, MIDI notes or
+    /// pitch tracking.
+    #[id = "max_gen_hz"]
+    pub max_gen_hz: FloatParam,
 
-    impl ProcessContext<SubrouRs> for DummyContext {
-        fn plugin_api(&self) -> PluginApi { PluginApi::Vst3 }
-        fn execute_background(&self, _task: ()) {}
-        fn execute_gui(&self, _task: ()) {}
-        fn transport(&self) -> &Transport { unreachable!("transport unused") }
-        fn next_event(&mut self) -> Option<PluginNoteEvent<SubrouRs>> { None }
-        fn send_event(&mut self, _event: PluginNoteEvent<SubrouRs>) {}
-        fn set_latency_samples(&self, _samples: u32) {}
-        fn set_current_voice_capacity(&self, _capacity: u32) {}
-    }
+    /// Portamento time between pitches, from `pitch` or the tracked pitch, `0` jumps straight
+    /// to the new one.
+    #[id = "glide_ms"]
+    pub glide_ms: FloatParam,
 
-    #[test]
-    fn test_process_silence() {
-        let mut plugin = SubrouRs::default();
-        plugin.params.post_gain.smoothed.reset(plugin.params.post_gain.value());
-        plugin.params.pitch.smoothed.reset(plugin.params.pitch.value());
-        let mut left = vec![0.0_f32; 64];
-        let mut right = vec![0.0_f32; 64];
-        let mut buffer = Buffer::default();
-        unsafe { buffer.set_slices(64, |out| *out = vec![&mut left, &mut right]) };
-        let mut aux = AuxiliaryBuffers { inputs: &mut [], outputs: &mut [] };
-        let mut ctx = DummyContext;
-        plugin.process(&mut buffer, &mut aux, &mut ctx);
-        for ch in buffer.as_slice() {
-            assert!(ch.iter().all(|&s| s == 0.0));
-        }
-    }
+    /// Whether glides move in equal steps of Hz or of cents.
+    #[id = "glide_curve"]
+    pub glide_curve: EnumParam<GlideCurve>,
 
-    #[test]
-    fn test_process_generates_audio() {
-        let mut plugin = SubrouRs::default();
-        plugin.params.post_gain.smoothed.reset(plugin.params.post_gain.value());
-        plugin.params.pitch.smoothed.reset(plugin.params.pitch.value());
-        let mut left = vec![1.0_f32; 64];
-        let mut right = vec![1.0_f32; 64];
-        let mut buffer = Buffer::default();
-        unsafe { buffer.set_slices(64, |out| *out = vec![&mut left, &mut right]) };
-        let mut aux = AuxiliaryBuffers { inputs: &mut [], outputs: &mut [] };
-        let mut ctx = DummyContext;
-        plugin.process(&mut buffer, &mut aux, &mut ctx);
-        let slices = buffer.as_slice();
-        assert!(slices[0].iter().any(|&s| s != 1.0));
-        assert_eq!(slices[0], slices[1]);
+    /// Follow the pitch of the input instead of using the fixed pitch, as long as a pitch is
+    /// detected.
+    #[id = "track_pitch"]
+    pub track_pitch: BoolParam,
+
+    /// Gradually pull the saw's phase onto the input's component at the generated pitch, so the
+    /// sub reinforces the source instead of cancelling it.
+    #[id = "phase_align"]
+    pub phase_align: BoolParam,
+
+    /// Restart the saw on every positive going zero crossing of the input's low band, keeping
+    /// it locked to a monophonic bass. The low pass makes the sub lag the input slightly.
+    #[id = "zero_cross_sync"]
+    pub zero_cross_sync: BoolParam,
+
+    /// Output channel, `0` for all channels or 1-based channel index.
+    #[id = "out_channel"]
+    pub out_channel: IntParam,
+
+    /// Time the detector takes to rise by 6 dB.
+    #[id = "attack_6db_ms"]
+    pub attack_6db_ms: FloatParam,
+
+    /// Time the detector takes to fall by 6 dB.
+    #[id = "release_6db_ms"]
+    pub release_6db_ms: FloatParam,
+
+    /// Run the detector in decibels, which follows loudness changes more evenly across levels.
+    #[id = "detect_log"]
+    pub detect_log: BoolParam,
+
+    /// Gain applied to the signal going into the detector only, so quiet sources still open the
+    /// gate and drive the envelope. The audio itself is left alone.
+    #[id = "detector_gain_db"]
+    pub detector_gain_db: FloatParam,
+
+    /// Low edge of the band the detector listens to, the lowest setting leaves it open.
+    #[id = "key_low_hz"]
+    pub key_low_hz: FloatParam,
+
+    /// High edge of the band the detector listens to, the highest setting leaves it open.
+    #[id = "key_high_hz"]
+    pub key_high_hz: FloatParam,
+
+    /// Shape of the detector's release. The linear and S-curve releases take the detector
+    /// release time to fall all the way to silence.
+    #[id = "release_curve"]
+    pub release_curve: EnumParam<ReleaseCurve>,
+
+    /// Envelope level above which the noise gate opens.
+    #[id = "open_db"]
+    pub open_db: FloatParam,
+
+    /// Envelope level below which the noise gate closes again. Kept below `open_db` so the gate
+    /// doesn't chatter around a single threshold.
+    #[id = "close_db"]
+    pub close_db: FloatParam,
+
+    /// Soft knee around the open threshold. A nonzero knee fades the sub in and out instead of
+    /// switching it, the close threshold is only used by the hard gate.
+    #[id = "gate_knee_db"]
+    pub gate_knee_db: FloatParam,
+
+    /// Exponent applied to the envelope before it is used as the sub's gain. Values above one
+    /// expand the control signal so quiet passages get proportionally less sub.
+    #[id = "env_curve"]
+    pub env_curve: FloatParam,
+
+    /// Lowest gain the envelope can drop to, so the sub keeps humming along even in silence.
+    #[id = "env_floor"]
+    pub env_floor: FloatParam,
+
+    /// Extra one pole smoothing on the final gain curve, on top of the detector's attack and
+    /// release, for a gentler sub. `0` leaves the curve as it is.
+    #[id = "curve_smooth_ms"]
+    pub curve_smooth_ms: FloatParam,
+
+    /// Snap the generated pitch to the nearest note in `scale`.
+    #[id = "quantize"]
+    pub quantize: BoolParam,
+
+    /// Scale used for pitch quantization.
+    #[id = "scale"]
+    pub scale: EnumParam<Scale>,
+
+    /// Root note of `scale`, `0` for C up to `11` for B.
+    #[id = "root"]
+    pub root: IntParam,
+
+    /// Oscillator used for the generated saw.
+    #[id = "osc_quality"]
+    pub osc_quality: EnumParam<OscQuality>,
+
+    /// Precision the oscillator phases are accumulated in.
+    #[id = "precision"]
+    pub precision: EnumParam<Precision>,
+
+    /// Samples on either side of each edge the band limited saw smooths over. Two costs a bit
+    /// more and aliases less at high pitches.
+    #[id = "blep_width"]
+    pub blep_width: IntParam,
+
+    /// Level of the additive saw's fundamental, relative to a saw.
+    #[id = "h1"]
+    pub h1: FloatParam,
+
+    /// Level of the additive saw's second harmonic, relative to a saw.
+    #[id = "h2"]
+    pub h2: FloatParam,
+
+    /// Level of the additive saw's third harmonic, relative to a saw.
+    #[id = "h3"]
+    pub h3: FloatParam,
+
+    /// Level of the additive saw's fourth harmonic, relative to a saw. At the defaults the
+    /// additive saw is the full series, moving any of `h1` to `h4` makes it just these four.
+    #[id = "h4"]
+    pub h4: FloatParam,
+
+    /// Randomize the phases of the additive oscillator's first harmonics, picking offsets that
+    /// lower its peak level. This keeps the spectrum but changes the waveform's shape.
+    #[id = "phase_spread"]
+    pub phase_spread: FloatParam,
+
+    /// Stretch the harmonics of the additive oscillator apart, harmonic `n` lands on
+    /// `n * freq * (1 + stretch * n)` for metallic, bell-like subs. The stretched saw keeps only
+    /// its first three harmonics. Not applied to MIDI voices.
+    #[id = "stretch"]
+    pub stretch: FloatParam,
+
+    /// Level of an extra oscillator a fifth above the fundamental.
+    #[id = "fifth_level"]
+    pub fifth_level: FloatParam,
+
+    /// Level of an extra oscillator an octave above the fundamental.
+    #[id = "octave_level"]
+    pub octave_level: FloatParam,
+
+    /// Boost everything above this frequency going into the drive stage and cut it again
+    /// afterwards, so the saturation mostly hits the upper harmonics. `0` saturates the flat
+    /// signal.
+    #[id = "emphasis_hz"]
+    pub emphasis_hz: FloatParam,
+
+    /// Run the drive stage at four times the sample rate so its harmonics don't alias.
+    #[id = "oversample_shaper"]
+    pub oversample_shaper: BoolParam,
+
+    /// Curve of the drive stage's clipper.
+    #[id = "saturation_type"]
+    pub saturation_type: EnumParam<shaper::SaturationType>,
+
+    /// How hard the sub is pushed into a wavefolder ahead of the saturation, `0` turns it off.
+    #[id = "fold"]
+    pub fold: FloatParam,
+
+    /// Delay of a comb filter on the generated sub, `0` turns it off. The peaks land on
+    /// multiples of `1 / delay` with notches halfway between them.
+    #[id = "comb_delay_ms"]
+    pub comb_delay_ms: FloatParam,
+
+    /// Feedback of the comb filter, negative values move the peaks to odd multiples of half
+    /// `1 / delay`.
+    #[id = "comb_feedback"]
+    pub comb_feedback: FloatParam,
+
+    /// Center of a resonant band added on top of the generated sub, doubling the level there
+    /// for a vowel like color. `0` turns it off.
+    #[id = "formant_hz"]
+    pub formant_hz: FloatParam,
+
+    /// Resonance of the formant band, higher values narrow it.
+    #[id = "formant_q"]
+    pub formant_q: FloatParam,
+
+    /// Gain of a high shelf on the generated sub, negative values darken the saw. `0` leaves it
+    /// untouched.
+    #[id = "hi_shelf_gain_db"]
+    pub hi_shelf_gain_db: FloatParam,
+
+    /// Corner frequency of the high shelf.
+    #[id = "hi_shelf_hz"]
+    pub hi_shelf_hz: FloatParam,
+
+    /// Cutoff of a lowpass on the generated sub, modulated by routes to `ModDestination::Filter`.
+    /// The filter is off at 20 kHz.
+    #[id = "cutoff_hz"]
+    pub cutoff_hz: FloatParam,
+
+    /// Lowest cutoff the modulation can sweep the lowpass down to.
+    #[id = "cutoff_min_hz"]
+    pub cutoff_min_hz: FloatParam,
+
+    /// Highest cutoff the modulation can sweep the lowpass up to.
+    #[id = "cutoff_max_hz"]
+    pub cutoff_max_hz: FloatParam,
+
+    /// How the channels are combined for the linked envelope and the pitch tracker.
+    #[id = "detector_mono_mode"]
+    pub detector_mono_mode: EnumParam<DetectorMonoMode>,
+
+    /// Input the sub is generated from. The aux input is always followed as a linked mono sum.
+    #[id = "source"]
+    pub source: EnumParam<InputSource>,
+
+    /// Share a single envelope, taken from the average of all channels, between all channels.
+    /// Otherwise every channel follows its own input.
+    #[id = "link_channels"]
+    pub link_channels: BoolParam,
+
+    /// Blend the envelope with a fast follower on the input's high band, so the sub also picks
+    /// up the source's micro dynamics.
+    #[id = "crossfeed"]
+    pub crossfeed: FloatParam,
+
+    /// Scale the sub so its RMS level matches the input's RMS times the post gain.
+    #[id = "auto_level"]
+    pub auto_level: BoolParam,
+
+    /// Hold the envelope at its current level instead of following the input.
+    #[id = "freeze"]
+    pub freeze: BoolParam,
+
+    /// Collapse the output to mono below this frequency, `0` disables the mono maker.
+    #[id = "mono_below_hz"]
+    pub mono_below_hz: FloatParam,
+
+    /// Widen the generated sub by adding an all-passed copy of it to the left channel and
+    /// subtracting it from the right. The mono sum stays the same, and the mono maker still
+    /// collapses everything below `mono_below_hz`.
+    #[id = "decorrelate"]
+    pub decorrelate: FloatParam,
+
+    /// Delay the generated sub on the right channel for a sense of width. The mono maker still
+    /// collapses everything below `mono_below_hz`, so only the upper harmonics get spread.
+    #[id = "haas_ms"]
+    pub haas_ms: FloatParam,
+
+    /// Play the saw from MIDI notes instead of following the input. The pitch follows the last
+    /// pressed note and the level follows a note envelope.
+    #[id = "midi_mode"]
+    pub midi_mode: BoolParam,
+
+    /// Attack time of the note envelope in MIDI mode.
+    #[id = "note_attack_ms"]
+    pub note_attack_ms: FloatParam,
+
+    /// Release time of the note envelope in MIDI mode.
+    #[id = "note_release_ms"]
+    pub note_release_ms: FloatParam,
+
+    /// Fire a decaying sine at `pitch` on every transient of the input instead of following its
+    /// envelope, for tuned kick subs.
+    #[id = "kick_mode"]
+    pub kick_mode: BoolParam,
+
+    /// Time constant of the kick's sweep down to `pitch`, `0` plays it at a fixed pitch.
+    #[id = "kick_pitch_decay_ms"]
+    pub kick_pitch_decay_ms: FloatParam,
+
+    /// How long the kick takes to fade out.
+    #[id = "kick_amp_decay_ms"]
+    pub kick_amp_decay_ms: FloatParam,
+
+    /// Transients within this time of the last kick don't fire a new one.
+    #[id = "retrigger_ms"]
+    pub retrigger_ms: FloatParam,
+
+    /// Shape the sub with its own ADSR instead of the input envelope. The envelope starts when
+    /// the gate opens and releases when it closes, the input level only decides when.
+    #[id = "amp_env"]
+    pub amp_env: BoolParam,
+
+    /// Attack time of the `amp_env` envelope.
+    #[id = "amp_attack_ms"]
+    pub amp_attack_ms: FloatParam,
+
+    /// Decay time of the `amp_env` envelope down to the sustain level.
+    #[id = "amp_decay_ms"]
+    pub amp_decay_ms: FloatParam,
+
+    /// Level the `amp_env` envelope holds while the gate stays open.
+    #[id = "amp_sustain"]
+    pub amp_sustain: FloatParam,
+
+    /// Release time of the `amp_env` envelope once the gate closes.
+    #[id = "amp_release_ms"]
+    pub amp_release_ms: FloatParam,
+
+    /// How far a full MIDI pitch bend moves the notes, in semitones.
+    #[id = "bend_range"]
+    pub bend_range: IntParam,
+
+    /// How many notes can play at once in MIDI mode, the oldest note is stolen after that.
+    #[id = "max_voices"]
+    pub max_voices: IntParam,
+
+    /// Highest note the sub is generated for. MIDI notes above it are ignored, and a tracked
+    /// pitch above it mutes the sub. The default of `127` lets everything through.
+    #[id = "max_note"]
+    pub max_note: IntParam,
+
+    /// Add TPDF dither at the 24-bit LSB level to the output.
+    #[id = "dither"]
+    pub dither: BoolParam,
+
+    /// Seed for the dither, the `phase_spread` offsets and the `drift`, the same seed renders the
+    /// same output every time.
+    #[id = "seed"]
+    pub seed: IntParam,
+
+    /// Highpass the output at a few Hz to remove any DC offset. Turn it off to keep content
+    /// below that, DC included.
+    #[id = "dc_block"]
+    pub dc_block: BoolParam,
+
+    /// Level above which the output compressor starts reducing the gain.
+    #[id = "compressor_threshold_db"]
+    pub compressor_threshold_db: FloatParam,
+
+    /// Compression ratio above the threshold, `1` turns the compressor off.
+    #[id = "compressor_ratio"]
+    pub compressor_ratio: FloatParam,
+
+    /// Compress both channels with the same gain, taken from the louder one, so the stereo image
+    /// doesn't shift.
+    #[id = "stereo_link"]
+    pub stereo_link: BoolParam,
+
+    /// Lookahead peak limiter on the output.
+    #[id = "limiter"]
+    pub limiter: BoolParam,
+
+    /// Output ceiling of the limiter.
+    #[id = "limiter_ceiling_db"]
+    pub limiter_ceiling_db: FloatParam,
+
+    /// Soft knee of the limiter, the gain reduction eases in this far below the ceiling.
+    #[id = "limiter_knee_db"]
+    pub limiter_knee_db: FloatParam,
+
+    /// How far the limiter looks ahead for peaks. This is reported to the host as latency.
+    #[id = "limiter_lookahead_ms"]
+    pub limiter_lookahead_ms: FloatParam,
+
+    /// Run the limiter without lookahead so it adds no latency. The gain reacts instantly and
+    /// recovers quickly, which distorts fast peaks more.
+    #[id = "limiter_zero_latency"]
+    pub limiter_zero_latency: BoolParam,
+
+    /// The generated sub fades in over this time after the plugin is reset or activated.
+    #[id = "fade_in_ms"]
+    pub fade_in_ms: FloatParam,
+
+    /// Host bypass. Switching it crossfades between the processed and the unprocessed signal.
+    #[id = "bypass"]
+    pub bypass: BoolParam,
+
+    /// Trim the unprocessed signal to the RMS level of the processed one while bypassed, so an
+    /// A/B comparison isn't just a comparison of loudness.
+    #[id = "bypass_match"]
+    pub bypass_match: BoolParam,
+
+    /// Output the signal the envelope detector sees instead of the processed audio.
+    #[id = "listen"]
+    pub listen: BoolParam,
+}
+
+impl Default for SubrouRs {
+    fn default() -> Self {
+        Self {
+            params: Arc::new(SubrouRsParams::default()),
+            sample_rate: 44100.0,
+            followers: vec![EnvelopeFollower::new(10.0, 10.0, 44100.0); 2],
+            crossfeed_hpf: Biquad::highpass(CROSSFEED_HZ, filter::BUTTERWORTH_Q, 44100.0),
+            crossfeed_follower: EnvelopeFollower::new(1.0, 5.0, 44100.0),
+            auto_level_input: auto_level_meter(44100.0),
+            bypass_match_dry: auto_level_meter(44100.0),
+            bypass_match_wet: auto_level_meter(44100.0),
+            auto_level_sub: auto_level_meter(44100.0),
+            gates: vec![GateState::default(); 2],
+            curve_smoothers: vec![0.0; 2],
+            saws: SawStack::default(),
+            sync_lpf: Biquad::lowpass(SYNC_LOWPASS_HZ, filter::BUTTERWORTH_Q, 44100.0),
+            sync_last: 0.0,
+            phase_detector: PhaseDetector::new(PHASE_ALIGN_DETECT_MS, 44100.0),
+            crossovers: vec![Crossover::new(100.0, 44100.0); 2],
+            voices: VoicePool::default(),
+            pitch_bend: 0.0,
+            transients: TransientDetector::new(44100.0),
+            kick: KickVoice::default(),
+            glide: Glide::default(),
+            dc_blockers: vec![DcBlocker::new(filter::DC_BLOCK_HZ, 44100.0); 2],
+            amp_env: Adsr::new(5.0, 200.0, 1.0, 100.0),
+            amp_env_open: false,
+            pitch_tracker: PitchTracker::new(44100.0),
+            detected_pitch: Arc::new(AtomicF32::new(0.0)),
+            correlation_meter: CorrelationMeter::new(44100.0),
+            output_correlation: Arc::new(AtomicF32::new(0.0)),
+            clipped: Arc::new(AtomicBool::new(false)),
+            true_peak_meters: vec![TruePeakMeter::default(); 2],
+            true_peak: Arc::new(AtomicF32::new(0.0)),
+            current_gain: Arc::new(AtomicF32::new(0.0)),
+            rng: XorShift32::default(),
+            drift_noise: SmoothNoise::default(),
+            harmonic_offsets: harmonic_offsets(&mut XorShift32::default()),
+            seed_override: None,
+            partials: [Oscillator::default(); ADDITIVE_SAW.len()],
+            compressor: Compressor::new(44100.0),
+            limiter: Limiter::new(2, max_lookahead_samples(44100.0)),
+            decorrelator: decorrelator(44100.0),
+            haas: DelayLine::new(max_haas_samples(44100.0)),
+            emphasis: shaper::Emphasis::new(1000.0, 44100.0),
+            oversampler: shaper::Oversampler::new(44100.0),
+            comb: CombFilter::new(max_comb_delay_samples(44100.0)),
+            formant: Biquad::default(),
+            hi_shelf: Biquad::default(),
+            sub_lowpass: Biquad::default(),
+            filter_cutoff: MAX_CUTOFF_HZ,
+            key_filters: vec![KeyFilter::default(); 2],
+            mod_matrix: ModMatrix::default(),
+            detector_channels: DetectorChannels::default(),
+            custom_curve: None,
+            custom_curve_pos: 0,
+            channel_trims: [0.0; MAX_TRIM_CHANNELS],
+            // Only `reset()` starts a fade in
+            fade_in_pos: usize::MAX,
+            bypass_mix: 0.0,
+            aux_mono: Vec::new(),
+            dry_scratch: Vec::new(),
+            latency: 0,
+        }
+    }
+}
+
+/// Detector attack or release, the default matches a 10 ms 10 to 90% rise time.
+fn detector_time_param(name: &str) -> FloatParam {
+    FloatParam::new(
+        name,
+        10.0 * std::f32::consts::LN_2 / 2.2,
+        FloatRange::Skewed {
+            min: 0.0,
+            max: 500.0,
+            factor: FloatRange::skew_factor(-2.0),
+        },
+    )
+    .with_unit(" ms/6 dB")
+    .with_value_to_string(formatters::v2s_f32_rounded(1))
+}
+
+impl Default for SubrouRsParams {
+    fn default() -> Self {
+        Self {
+            post_gain: FloatParam::new(
+                "Post Gain",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: util::db_to_gain(12.0),
+                    factor: FloatRange::gain_skew_factor(util::MINUS_INFINITY_DB, 12.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(10.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            dry_gain: FloatParam::new(
+                "Dry Gain",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: util::db_to_gain(12.0),
+                    factor: FloatRange::gain_skew_factor(util::MINUS_INFINITY_DB, 12.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(10.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            amount: FloatParam::new("Amount", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit(" %")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            pitch: FloatParam::new(
+                "Pitch",
+                440.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(20.0))
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            fine_tune: FloatParam::new(
+                "Fine Tune",
+                0.0,
+                FloatRange::Linear { min: -100.0, max: 100.0 },
+            )
+            .with_unit(" ct")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            drift: FloatParam::new("Drift", 0.0, FloatRange::Linear { min: 0.0, max: 50.0 })
+                .with_unit(" ct")
+                .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            max_gen_hz: FloatParam::new(
+                "Max Frequency",
+                2000.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            glide_ms: FloatParam::new(
+                "Glide",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            glide_curve: EnumParam::new("Glide Curve", GlideCurve::Exponential),
+            track_pitch: BoolParam::new("Track Pitch", false),
+            phase_align: BoolParam::new("Phase Align", false),
+            zero_cross_sync: BoolParam::new("Zero Crossing Sync", false),
+            out_channel: IntParam::new(
+                "Output Channel",
+                0,
+                IntRange::Linear { min: 0, max: 10 },
+            ),
+            attack_6db_ms: detector_time_param("Detector Attack"),
+            release_6db_ms: detector_time_param("Detector Release"),
+            detect_log: BoolParam::new("Log Detector", false),
+            detector_gain_db: FloatParam::new(
+                "Detector Gain",
+                0.0,
+                FloatRange::Linear { min: -12.0, max: 36.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            key_low_hz: cutoff_param("Key Low", MIN_CUTOFF_HZ),
+            key_high_hz: cutoff_param("Key High", MAX_CUTOFF_HZ),
+            release_curve: EnumParam::new("Release Curve", ReleaseCurve::Exponential),
+            open_db: FloatParam::new(
+                "Gate Open",
+                -60.0,
+                FloatRange::Linear { min: util::MINUS_INFINITY_DB, max: 0.0 },
+            )
+            .with_unit(" dB"),
+            close_db: FloatParam::new(
+                "Gate Close",
+                -70.0,
+                FloatRange::Linear { min: util::MINUS_INFINITY_DB, max: 0.0 },
+            )
+            .with_unit(" dB"),
+            gate_knee_db: FloatParam::new(
+                "Gate Knee",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 24.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            env_curve: FloatParam::new(
+                "Envelope Curve",
+                1.0,
+                FloatRange::Linear { min: 0.25, max: 4.0 },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            env_floor: FloatParam::new(
+                "Envelope Floor",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" %")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            curve_smooth_ms: FloatParam::new(
+                "Curve Smoothing",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 200.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            quantize: BoolParam::new("Quantize", false),
+            scale: EnumParam::new("Scale", Scale::Chromatic),
+            root: IntParam::new("Root", 0, IntRange::Linear { min: 0, max: 11 }),
+            osc_quality: EnumParam::new("Oscillator", OscQuality::Additive),
+            precision: EnumParam::new("Precision", Precision::Standard),
+            blep_width: IntParam::new("BLEP Width", 1, IntRange::Linear { min: 1, max: 2 }),
+            h1: FloatParam::new("Harmonic 1", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            h2: FloatParam::new("Harmonic 2", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            h3: FloatParam::new("Harmonic 3", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            h4: FloatParam::new("Harmonic 4", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            phase_spread: FloatParam::new(
+                "Phase Spread",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" %")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            stretch: FloatParam::new(
+                "Stretch",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 0.1,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+            fifth_level: FloatParam::new(
+                "Fifth Level",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" %")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            octave_level: FloatParam::new(
+                "Octave Level",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" %")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            emphasis_hz: FloatParam::new(
+                "Emphasis",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            oversample_shaper: BoolParam::new("Oversample Drive", false),
+            saturation_type: EnumParam::new("Saturation", shaper::SaturationType::Tanh),
+            fold: FloatParam::new("Fold", 0.0, FloatRange::Linear { min: 0.0, max: 10.0 }),
+            comb_delay_ms: FloatParam::new(
+                "Comb Delay",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: MAX_COMB_DELAY_MS },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            comb_feedback: FloatParam::new(
+                "Comb Feedback",
+                0.5,
+                FloatRange::Linear { min: -0.95, max: 0.95 },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            formant_hz: FloatParam::new(
+                "Formant",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 4000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            formant_q: FloatParam::new(
+                "Formant Q",
+                4.0,
+                FloatRange::Skewed {
+                    min: 0.5,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            hi_shelf_gain_db: FloatParam::new(
+                "High Shelf Gain",
+                0.0,
+                FloatRange::Linear { min: -24.0, max: 12.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            hi_shelf_hz: FloatParam::new(
+                "High Shelf Frequency",
+                1000.0,
+                FloatRange::Skewed {
+                    min: 100.0,
+                    max: 10000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            cutoff_hz: cutoff_param("Cutoff", MAX_CUTOFF_HZ),
+            cutoff_min_hz: cutoff_param("Cutoff Min", 20.0),
+            cutoff_max_hz: cutoff_param("Cutoff Max", MAX_CUTOFF_HZ),
+            detector_mono_mode: EnumParam::new("Detector Mono Mode", DetectorMonoMode::Average),
+            source: EnumParam::new("Source", InputSource::Main),
+            link_channels: BoolParam::new("Link Channels", true),
+            crossfeed: FloatParam::new(
+                "Crossfeed",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" %")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            auto_level: BoolParam::new("Auto Level", false),
+            freeze: BoolParam::new("Freeze", false),
+            mono_below_hz: FloatParam::new(
+                "Mono Below",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            decorrelate: FloatParam::new(
+                "Decorrelate",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" %")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            haas_ms: FloatParam::new(
+                "Haas Delay",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: MAX_HAAS_MS },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            midi_mode: BoolParam::new("MIDI Mode", false),
+            note_attack_ms: FloatParam::new(
+                "Note Attack",
+                5.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            note_release_ms: FloatParam::new(
+                "Note Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            kick_mode: BoolParam::new("Kick Mode", false),
+            kick_pitch_decay_ms: FloatParam::new(
+                "Kick Pitch Decay",
+                20.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            kick_amp_decay_ms: FloatParam::new(
+                "Kick Decay",
+                300.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            retrigger_ms: FloatParam::new(
+                "Kick Retrigger",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1000.0 },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            amp_env: BoolParam::new("Amp Envelope", false),
+            amp_attack_ms: FloatParam::new(
+                "Amp Attack",
+                5.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            amp_decay_ms: FloatParam::new(
+                "Amp Decay",
+                200.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            amp_sustain: FloatParam::new(
+                "Amp Sustain",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            amp_release_ms: FloatParam::new(
+                "Amp Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            bend_range: IntParam::new("Bend Range", 2, IntRange::Linear { min: 0, max: 24 })
+                .with_unit(" st"),
+            max_voices: IntParam::new(
+                "Max Voices",
+                4,
+                IntRange::Linear { min: 1, max: voice::MAX_VOICES as i32 },
+            ),
+            max_note: IntParam::new("Max Note", 127, IntRange::Linear { min: 0, max: 127 }),
+            dither: BoolParam::new("Dither", false),
+            seed: IntParam::new("Seed", 0, IntRange::Linear { min: 0, max: 9999 }),
+            dc_block: BoolParam::new("DC Block", true),
+            compressor_threshold_db: FloatParam::new(
+                "Compressor Threshold",
+                0.0,
+                FloatRange::Linear { min: -60.0, max: 0.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            compressor_ratio: FloatParam::new(
+                "Compressor Ratio",
+                1.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            stereo_link: BoolParam::new("Stereo Link", true),
+            limiter: BoolParam::new("Limiter", false),
+            limiter_ceiling_db: FloatParam::new(
+                "Limiter Ceiling",
+                -0.3,
+                FloatRange::Linear { min: -24.0, max: 0.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            limiter_knee_db: FloatParam::new(
+                "Limiter Knee",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 12.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            limiter_lookahead_ms: FloatParam::new(
+                "Limiter Lookahead",
+                5.0,
+                FloatRange::Linear { min: 0.0, max: MAX_LOOKAHEAD_MS },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            limiter_zero_latency: BoolParam::new("Zero Latency Limiter", false),
+            fade_in_ms: FloatParam::new(
+                "Fade In",
+                5.0,
+                FloatRange::Linear { min: 0.0, max: 100.0 },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            bypass: BoolParam::new("Bypass", false).make_bypass(),
+            bypass_match: BoolParam::new("Loudness Matched Bypass", false),
+            listen: BoolParam::new("Listen", false),
+        }
+    }
+}
+
+impl SubrouRs {
+    fn osc_settings(&self) -> OscSettings {
+        let phase_spread = self.params.phase_spread.value();
+        let levels = [
+            self.params.h1.value(),
+            self.params.h2.value(),
+            self.params.h3.value(),
+            self.params.h4.value(),
+        ];
+        OscSettings {
+            quality: self.params.osc_quality.value(),
+            offsets: (phase_spread > 0.0)
+                .then(|| self.harmonic_offsets.map(|offset| offset * phase_spread)),
+            fifth: self.params.fifth_level.value(),
+            octave: self.params.octave_level.value(),
+            max_terms: amount_macro(self.params.amount.value()).2,
+            blep_width: self.params.blep_width.value() as u32,
+            harmonics: (levels != DEFAULT_HARMONIC_LEVELS)
+                .then(|| std::array::from_fn(|i| SAW_HARMONICS[i] * levels[i])),
+        }
+    }
+
+    /// Render exactly one period of the current oscillator settings into `length` samples, for
+    /// use as a `Wavetable`. The fifth doesn't repeat within one period of the fundamental, so
+    /// it's left out, and so is the `stretch` for the same reason.
+    pub fn capture_cycle(&self, length: usize) -> Vec<f32> {
+        let settings = OscSettings {
+            fifth: 0.0,
+            ..self.osc_settings()
+        };
+        let phase_inc = 2.0 * std::f32::consts::PI / length as f32;
+        let mut saws = SawStack::default();
+        (0..length)
+            .map(|_| settings.render(saws.next(1.0, length as f32), phase_inc))
+            .collect()
+    }
+
+    /// Total latency in samples caused by the current settings, this is what gets reported to
+    /// the host. The limiter's lookahead is the only source of latency, the drive stage's
+    /// oversampling filters are minimum phase and don't add any whole samples.
+    pub fn latency_samples(&self) -> u32 {
+        if self.params.limiter.value() && !self.params.limiter_zero_latency.value() {
+            (self.params.limiter_lookahead_ms.value() * 0.001 * self.sample_rate).round() as u32
+        } else {
+            0
+        }
+    }
+
+    /// Change the sample rate and recompute everything derived from it. This keeps the channel
+    /// counts but clears the filter and envelope state.
+    pub fn update_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for follower in &mut self.followers {
+            *follower = EnvelopeFollower::new(10.0, 10.0, sample_rate);
+        }
+        for crossover in &mut self.crossovers {
+            *crossover = Crossover::new(100.0, sample_rate);
+        }
+        for blocker in &mut self.dc_blockers {
+            blocker.set_frequency(filter::DC_BLOCK_HZ, sample_rate);
+        }
+        self.crossfeed_hpf = Biquad::highpass(CROSSFEED_HZ, filter::BUTTERWORTH_Q, sample_rate);
+        self.crossfeed_follower = EnvelopeFollower::new(1.0, 5.0, sample_rate);
+        self.sync_lpf = Biquad::lowpass(SYNC_LOWPASS_HZ, filter::BUTTERWORTH_Q, sample_rate);
+        self.sync_last = 0.0;
+        self.phase_detector = PhaseDetector::new(PHASE_ALIGN_DETECT_MS, sample_rate);
+        self.auto_level_input = auto_level_meter(sample_rate);
+        self.auto_level_sub = auto_level_meter(sample_rate);
+        self.bypass_match_dry = auto_level_meter(sample_rate);
+        self.bypass_match_wet = auto_level_meter(sample_rate);
+        self.compressor = Compressor::new(sample_rate);
+        let num_channels = self.limiter.num_channels();
+        self.limiter = Limiter::new(num_channels, max_lookahead_samples(sample_rate));
+        self.pitch_tracker = PitchTracker::new(sample_rate);
+        self.transients = TransientDetector::new(sample_rate);
+        self.comb = CombFilter::new(max_comb_delay_samples(sample_rate));
+        self.correlation_meter = CorrelationMeter::new(sample_rate);
+        self.decorrelator = decorrelator(sample_rate);
+        self.haas = DelayLine::new(max_haas_samples(sample_rate));
+        self.oversampler = shaper::Oversampler::new(sample_rate);
+    }
+
+    /// Snap all parameter smoothers to their current values, so the next processed sample uses
+    /// them without ramping.
+    pub fn reset_smoothers(&mut self) {
+        self.params.post_gain.smoothed.reset(self.params.post_gain.value());
+        self.params.dry_gain.smoothed.reset(self.params.dry_gain.value());
+        self.params.pitch.smoothed.reset(self.params.pitch.value());
+    }
+
+    /// Set every parameter back to its default, like loading an init preset. Only the host can
+    /// change parameter values, so this goes through the editor's context as a set of gestures.
+    pub fn reset_params_to_default(&self, context: &dyn GuiContext) {
+        for (_, param, _) in self.params.param_map() {
+            // SAFETY: The pointers come from `self.params`, which outlives this loop
+            unsafe {
+                context.raw_begin_set_parameter(param);
+                context.raw_set_parameter_normalized(param, param.default_normalized_value());
+                context.raw_end_set_parameter(param);
+            }
+        }
+    }
+
+    /// The pitch currently followed by pitch tracking, `None` if tracking is off or the input
+    /// is unpitched.
+    pub fn detected_pitch(&self) -> Option<f32> {
+        let pitch = self.detected_pitch.load(Ordering::Relaxed);
+        (pitch > 0.0).then_some(pitch)
+    }
+
+    /// Correlation between the left and right output, from `-1` (out of phase) to `+1` (mono).
+    /// Mono output always reads `+1`.
+    pub fn output_correlation(&self) -> f32 {
+        self.output_correlation.load(Ordering::Relaxed)
+    }
+
+    /// Whether any output sample went past ±1.0 since the last `reset_clip`.
+    pub fn clipped(&self) -> bool {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_clip(&self) {
+        self.clipped.store(false, Ordering::Relaxed);
+    }
+
+    /// Highest output level during the last block including the peaks between samples, as
+    /// estimated with 4x oversampling.
+    pub fn true_peak(&self) -> f32 {
+        self.true_peak.load(Ordering::Relaxed)
+    }
+
+    /// Gain the sub's gain stage applied to the oscillator at the end of the last block in dB, for
+    /// a gain reduction style meter. This is the post gain with its modulation, on top of the
+    /// input envelope the sub follows. `0` is full level, so ducking the sub with an inverted
+    /// `Envelope` route to `Gain` reads as negative values, and a muted sub as negative infinity.
+    pub fn current_gain_db(&self) -> f32 {
+        util::gain_to_db(self.current_gain.load(Ordering::Relaxed))
+    }
+
+    /// Cutoff of the lowpass on the generated sub during the last block, after modulation.
+    pub fn filter_cutoff(&self) -> f32 {
+        self.filter_cutoff
+    }
+
+    /// Reseed every random feature with `seed` instead of the `seed` parameter, for reproducible
+    /// offline renders. The generators restart right away and again on every reset.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed_override = Some(seed);
+        self.reseed();
+    }
+
+    fn reseed(&mut self) {
+        let seed = self.seed_override.unwrap_or(self.params.seed.value() as u32);
+        self.rng = seeded_rng(seed);
+        self.harmonic_offsets = harmonic_offsets(&mut seeded_rng(seed));
+        self.drift_noise = SmoothNoise::new(seeded_rng(seed));
+    }
+
+    /// Modulation routes, these are not exposed as plugin parameters.
+    pub fn mod_matrix_mut(&mut self) -> &mut ModMatrix {
+        &mut self.mod_matrix
+    }
+
+    /// Channel selection for the linked detector, these are not exposed as plugin parameters.
+    pub fn detector_channels_mut(&mut self) -> &mut DetectorChannels {
+        &mut self.detector_channels
+    }
+
+    /// Let every channel into the linked detector at the weight in `detector_weights`, channels
+    /// past the end count fully. Use with `DetectorMonoMode::WeightedSum` to keep the weights
+    /// from being normalized.
+    pub fn set_detector_weights(&mut self, detector_weights: Vec<f32>) {
+        self.detector_channels = DetectorChannels {
+            mask: u32::MAX,
+            weights: detector_weights,
+        };
+    }
+
+    /// Per channel trim of the added sub in dB, these are not exposed as plugin parameters.
+    pub fn channel_trims_mut(&mut self) -> &mut [f32; MAX_TRIM_CHANNELS] {
+        &mut self.channel_trims
+    }
+
+    /// Drive the sub's gain from `curve` instead of the input envelope, or go back to the envelope
+    /// with `None`. The curve is played from the start across consecutive blocks and holds its
+    /// last value once it runs out.
+    pub fn set_custom_curve(&mut self, curve: Option<Vec<f32>>) {
+        self.custom_curve = curve;
+        self.custom_curve_pos = 0;
+    }
+
+    /// Run the processing chain on a mono signal and return the resulting mono output, i.e. the
+    /// (scaled) input with the generated saw added.
+    pub fn process_mono(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_channels(&mut [output.as_mut_slice()], || None);
+        output
+    }
+
+    /// Like `process_mono`, but the input is processed in blocks of `block` samples the way a
+    /// host would. The result should not depend on the block size.
+    pub fn process_in_blocks(&mut self, input: &[f32], block: usize) -> Vec<f32> {
+        let mut output = input.to_vec();
+        for chunk in output.chunks_mut(block.max(1)) {
+            self.process_channels(&mut [chunk], || None);
+        }
+        output
+    }
+
+    /// The actual processing, `next_event` yields the block's note events in order. Returns the
+    /// level the generated sub is played at for every sample.
+    fn process_channels(
+        &mut self,
+        slices: &mut [&mut [f32]],
+        next_event: impl FnMut() -> Option<PluginNoteEvent<Self>>,
+    ) -> Vec<f32> {
+        // Processing continues while bypassed so notes and envelopes are current when the
+        // processed signal fades back in
+        let bypass = self.params.bypass.value();
+        let crossfade = bypass || self.bypass_mix > 0.0;
+        let loudness_match = self.params.bypass_match.value();
+        if crossfade || loudness_match {
+            let num_samples = slices.first().map_or(0, |channel| channel.len());
+            self.grow_dry_scratch(slices.len(), num_samples);
+            for (dry, channel) in self.dry_scratch.iter_mut().zip(slices.iter()) {
+                dry[..channel.len()].copy_from_slice(channel);
+            }
+        }
+        let mut sub_level = self.process_wet(slices, next_event);
+        let mut dry_trim = 1.0;
+        if loudness_match {
+            let num_channels = slices.len() as f32;
+            for i in 0..sub_level.len() {
+                let dry: f32 =
+                    self.dry_scratch.iter().take(slices.len()).map(|dry| dry[i]).sum();
+                let wet: f32 = slices.iter().map(|channel| channel[i]).sum();
+                self.bypass_match_dry.next(dry / num_channels);
+                self.bypass_match_wet.next(wet / num_channels);
+            }
+            let dry_rms = self.bypass_match_dry.value();
+            if dry_rms > 0.0 {
+                dry_trim = (self.bypass_match_wet.value() / dry_rms).min(MAX_AUTO_LEVEL_GAIN);
+            }
+        }
+        if crossfade {
+            let unprocessed = &self.dry_scratch;
+            let target = if bypass { 1.0 } else { 0.0 };
+            let step = 1.0 / (BYPASS_FADE_MS * 0.001 * self.sample_rate).max(1.0);
+            for (i, level) in sub_level.iter_mut().enumerate() {
+                self.bypass_mix += (target - self.bypass_mix).clamp(-step, step);
+                for (channel, dry) in slices.iter_mut().zip(unprocessed) {
+                    channel[i] = self.bypass_mix * dry_trim * dry[i]
+                        + (1.0 - self.bypass_mix) * channel[i];
+                }
+                *level *= 1.0 - self.bypass_mix;
+            }
+            let gain = self.current_gain.load(Ordering::Relaxed);
+            self.current_gain.store(gain * (1.0 - self.bypass_mix), Ordering::Relaxed);
+        }
+        if slices.iter().any(|channel| channel.iter().any(|s| s.abs() > 1.0)) {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+        let mut true_peak = 0.0_f32;
+        for (meter, channel) in self.true_peak_meters.iter_mut().zip(slices.iter()) {
+            meter.reset_peak();
+            for &sample in channel.iter() {
+                meter.next(sample);
+            }
+            true_peak = true_peak.max(meter.peak());
+        }
+        self.true_peak.store(true_peak, Ordering::Relaxed);
+        sub_level
+    }
+
+    /// Make sure `dry_scratch` holds `num_samples` for every channel. Hosts shouldn't send blocks
+    /// larger than the maximum they announced, but if one does the buffers grow (and allocate)
+    /// rather than getting indexed out of bounds.
+    fn grow_dry_scratch(&mut self, num_channels: usize, num_samples: usize) {
+        let capacity = self.dry_scratch.first().map_or(0, Vec::len);
+        if num_samples > capacity {
+            nih_log!("Block of {num_samples} samples exceeds the maximum of {capacity}, growing");
+        }
+        let capacity = capacity.max(num_samples);
+        if self.dry_scratch.len() < num_channels {
+            self.dry_scratch.resize(num_channels, Vec::new());
+        }
+        for dry in &mut self.dry_scratch {
+            dry.resize(capacity, 0.0);
+        }
+    }
+
+    fn process_wet(
+        &mut self,
+        slices: &mut [&mut [f32]],
+        mut next_event: impl FnMut() -> Option<PluginNoteEvent<Self>>,
+    ) -> Vec<f32> {
+        let num_samples = slices.first().map_or(0, |channel| channel.len());
+        if num_samples == 0 {
+            return Vec::new();
+        }
+
+        // The aux input only belongs to the block `process` took it from
+        let aux_mono = std::mem::take(&mut self.aux_mono);
+        let from_aux = aux_mono.len() >= num_samples;
+        let mono = if from_aux {
+            aux_mono[..num_samples].to_vec()
+        } else {
+            detector_signal(
+                slices,
+                self.params.detector_mono_mode.value(),
+                &self.detector_channels,
+            )
+        };
+
+        // Envelope from mono input, or one envelope per channel when unlinked
+        let link = self.params.link_channels.value() || from_aux;
+        if self.followers.len() < slices.len() {
+            self.followers.resize(slices.len(), self.followers[0]);
+        }
+        let detector_attack = self.params.attack_6db_ms.value();
+        let detector_release = self.params.release_6db_ms.value();
+        let detector_mode = if self.params.detect_log.value() {
+            DetectorMode::Log
+        } else {
+            DetectorMode::Peak
+        };
+        let release_curve = self.params.release_curve.value();
+        for follower in &mut self.followers {
+            follower.set_6db_times(detector_attack, detector_release, self.sample_rate);
+            follower.set_release_curve(release_curve, detector_release, self.sample_rate);
+            follower.set_mode(detector_mode);
+        }
+        let detector_gain = util::db_to_gain(self.params.detector_gain_db.value());
+        let key_low_hz = self.params.key_low_hz.value();
+        let key_high_hz = self.params.key_high_hz.value();
+        for key_filter in &mut self.key_filters {
+            key_filter.set_band(
+                (key_low_hz > MIN_CUTOFF_HZ).then_some(key_low_hz),
+                (key_high_hz < MAX_CUTOFF_HZ).then_some(key_high_hz),
+                self.sample_rate,
+            );
+        }
+        // What the detector hears, the keyed and scaled mono signal or one signal per channel
+        let detected: Vec<Vec<f32>> = if link {
+            let key_filter = &mut self.key_filters[0];
+            vec![mono.iter().map(|&s| key_filter.process(s) * detector_gain).collect()]
+        } else {
+            slices
+                .iter()
+                .zip(&mut self.key_filters)
+                .map(|(channel, key_filter)| {
+                    channel.iter().map(|&s| key_filter.process(s) * detector_gain).collect()
+                })
+                .collect()
+        };
+        let freeze = self.params.freeze.value();
+        let mut curves: Vec<Vec<f32>> = if freeze {
+            // The followers keep their last level until the freeze is released
+            self.followers[..detected.len()]
+                .iter()
+                .map(|follower| vec![follower.value(); num_samples])
+                .collect()
+        } else {
+            detected
+                .iter()
+                .zip(&mut self.followers)
+                .map(|(signal, follower)| signal.iter().map(|&s| follower.next(s)).collect())
+                .collect()
+        };
+
+        // Crossfeed, the high band envelope always runs so its state stays current
+        let crossfeed = self.params.crossfeed.value();
+        for (i, &sample) in mono.iter().enumerate() {
+            let high = self
+                .crossfeed_follower
+                .next(self.crossfeed_hpf.process(sample) * detector_gain);
+            if crossfeed > 0.0 && !freeze {
+                for curve in curves.iter_mut() {
+                    curve[i] += crossfeed * (high - curve[i]);
+                }
+            }
+        }
+
+        // The envelope is taken from the unscaled input, so muting the dry signal still leaves
+        // the generated saw
+        for i in 0..num_samples {
+            let dry = self.params.dry_gain.smoothed.next();
+            for channel in slices.iter_mut() {
+                channel[i] *= dry;
+            }
+        }
+
+        // Gate with hysteresis to keep low level noise from generating a sub
+        let open = util::db_to_gain(self.params.open_db.value());
+        let close = util::db_to_gain(self.params.close_db.value()).min(open);
+        if self.gates.len() < curves.len() {
+            self.gates.resize(curves.len(), GateState::default());
+        }
+        let knee_db = self.params.gate_knee_db.value();
+        // The gate state is kept with the soft knee too, the amp envelope is triggered by the
+        // first channel's gate rather than by whatever level the curve ends up at
+        let mut gate_open = Vec::with_capacity(num_samples);
+        for (channel, (curve, gate)) in curves.iter_mut().zip(&mut self.gates).enumerate() {
+            for gain in curve.iter_mut() {
+                let is_open = gate.update(*gain, open, close);
+                if channel == 0 {
+                    gate_open.push(is_open);
+                }
+                if knee_db > 0.0 {
+                    *gain *= gate::soft_gate_gain(*gain, open, knee_db);
+                } else if !is_open {
+                    *gain = 0.0;
+                }
+            }
+        }
+
+        let env_curve = self.params.env_curve.value();
+        if env_curve != 1.0 {
+            for gain in curves.iter_mut().flatten() {
+                *gain = gain.powf(env_curve);
+            }
+        }
+        let env_floor = self.params.env_floor.value();
+        if env_floor > 0.0 {
+            for gain in curves.iter_mut().flatten() {
+                *gain = gain.max(env_floor);
+            }
+        }
+        let curve_smooth_ms = self.params.curve_smooth_ms.value();
+        if curve_smooth_ms > 0.0 {
+            let coeff = smoothing_coeff(curve_smooth_ms, self.sample_rate);
+            self.curve_smoothers.resize(curves.len(), 0.0);
+            for (curve, state) in curves.iter_mut().zip(&mut self.curve_smoothers) {
+                for gain in curve.iter_mut() {
+                    *state = envelope::flush_denormal(*state + coeff * (*gain - *state));
+                    *gain = *state;
+                }
+            }
+        }
+
+        // A user supplied curve replaces the detected envelope entirely
+        if let Some(custom) = &self.custom_curve {
+            let curve = (0..num_samples)
+                .map(|i| {
+                    let pos = (self.custom_curve_pos + i).min(custom.len().saturating_sub(1));
+                    custom.get(pos).copied().unwrap_or(0.0)
+                })
+                .collect();
+            self.custom_curve_pos += num_samples;
+            curves = vec![curve];
+        }
+
+        let phase_align = self.params.phase_align.value() && !self.params.midi_mode.value();
+        let tracked_pitch = if self.params.track_pitch.value() {
+            self.pitch_tracker.process(&mono, self.sample_rate)
+        } else {
+            None
+        };
+        self.detected_pitch.store(tracked_pitch.unwrap_or(0.0), Ordering::Relaxed);
+
+        if self.params.listen.value() {
+            for (i, channel) in slices.iter_mut().enumerate() {
+                channel.copy_from_slice(&detected[i.min(detected.len() - 1)]);
+            }
+            self.current_gain.store(0.0, Ordering::Relaxed);
+            return vec![0.0; num_samples];
+        }
+
+        // Generate saw wave with envelope gain
+        let quantize = self.params.quantize.value();
+        let scale = self.params.scale.value().intervals();
+        let root = self.params.root.value() as u8;
+        let max_gen_hz = self.params.max_gen_hz.value();
+        let glide_samples = (self.params.glide_ms.value() * 0.001 * self.sample_rate) as u32;
+        let glide_curve = self.params.glide_curve.value();
+        let fine_tune = cents_to_ratio(self.params.fine_tune.value());
+        let drift = self.params.drift.value();
+        let osc_settings = self.osc_settings();
+        let osc = |phase: f32, phase_inc: f32| osc_settings.osc(phase, phase_inc);
+        let render = |phases: [f32; 3], phase_inc: f32| osc_settings.render(phases, phase_inc);
+        let stretch = match osc_settings.quality {
+            OscQuality::Additive => self.params.stretch.value(),
+            _ => 0.0,
+        };
+        let emphasis_hz = self.params.emphasis_hz.value();
+        let oversample_shaper = self.params.oversample_shaper.value();
+        let saturation_type = self.params.saturation_type.value();
+        let fold = self.params.fold.value();
+        let comb_delay = self.params.comb_delay_ms.value() * 0.001 * self.sample_rate;
+        let comb_feedback = self.params.comb_feedback.value();
+        let formant_hz = self.params.formant_hz.value();
+        if formant_hz > 0.0 {
+            let formant_q = self.params.formant_q.value();
+            self.formant.set_bandpass(formant_hz, formant_q, self.sample_rate);
+        }
+        let hi_shelf_gain_db = self.params.hi_shelf_gain_db.value();
+        if hi_shelf_gain_db != 0.0 {
+            let hi_shelf_hz = self.params.hi_shelf_hz.value();
+            self.hi_shelf.set_high_shelf(hi_shelf_hz, hi_shelf_gain_db, self.sample_rate);
+        }
+        if emphasis_hz > 0.0 {
+            self.emphasis.set_frequency(emphasis_hz, self.sample_rate);
+        }
+        let cutoff_hz = self.params.cutoff_hz.value();
+        let cutoff_min_hz = self.params.cutoff_min_hz.value();
+        let cutoff_max_hz = self.params.cutoff_max_hz.value().max(cutoff_min_hz);
+        let (amount_gain, amount_drive, _) = amount_macro(self.params.amount.value());
+        let high_precision = self.params.precision.value() == Precision::HighPrecision;
+        let sample_rate = self.sample_rate;
+        let advance = |saws: &mut SawStack, freq: f32| {
+            if high_precision {
+                saws.next_f64(freq as f64, sample_rate as f64)
+            } else {
+                saws.next(freq, sample_rate)
+            }
+        };
+        let midi_mode = self.params.midi_mode.value();
+        let kick_mode = self.params.kick_mode.value() && !midi_mode;
+        let kick_pitch_decay_ms = self.params.kick_pitch_decay_ms.value();
+        let kick_amp_decay_ms = self.params.kick_amp_decay_ms.value();
+        let retrigger_ms = self.params.retrigger_ms.value();
+        let amp_env = self.params.amp_env.value() && !midi_mode && !kick_mode;
+        self.amp_env.attack_ms = self.params.amp_attack_ms.value();
+        self.amp_env.decay_ms = self.params.amp_decay_ms.value();
+        self.amp_env.sustain = self.params.amp_sustain.value();
+        self.amp_env.release_ms = self.params.amp_release_ms.value();
+        let zero_cross_sync = self.params.zero_cross_sync.value() && !midi_mode;
+        let phase_align_rate = smoothing_coeff(PHASE_ALIGN_MS, self.sample_rate);
+        let max_voices = self.params.max_voices.value() as usize;
+        let max_note = self.params.max_note.value() as u8;
+        // Allow for a tracker reading a little sharp of the highest note
+        let max_note_hz = util::f32_midi_note_to_freq(max_note as f32 + 0.5);
+        let note_gate = match tracked_pitch {
+            Some(tracked) if !midi_mode && tracked > max_note_hz => 0.0,
+            _ => 1.0,
+        };
+        let bend_range = self.params.bend_range.value() as f32;
+        let attack_ms = self.params.note_attack_ms.value();
+        let release_ms = self.params.note_release_ms.value();
+        for voice in self.voices.iter_mut() {
+            voice.adsr.attack_ms = attack_ms;
+            voice.adsr.release_ms = release_ms;
+        }
+        let mut saw = Vec::with_capacity(num_samples);
+        let mut sub_level = Vec::with_capacity(num_samples);
+        let mut gain_stage = 0.0;
+        let mut event = next_event();
+        for (i, &input_envelope) in curves[0].iter().enumerate() {
+            while let Some(note_event) = event {
+                if note_event.timing() > i as u32 {
+                    break;
+                }
+                match note_event {
+                    NoteEvent::NoteOn { note, .. } if note <= max_note => {
+                        let adsr = Adsr::new(attack_ms, 0.0, 1.0, release_ms);
+                        self.voices.note_on(note, max_voices, adsr);
+                    }
+                    NoteEvent::NoteOff { note, .. } => self.voices.note_off(note, self.sample_rate),
+                    NoteEvent::MidiPitchBend { value, .. } => self.pitch_bend = value * 2.0 - 1.0,
+                    NoteEvent::MidiCC { cc, value, .. } => self.mod_matrix.set_cc(cc, value),
+                    _ => (),
+                }
+                event = next_event();
+            }
+
+            if kick_mode
+                && self.transients.next(mono[i])
+                && self.kick.elapsed_ms() >= retrigger_ms
+            {
+                self.kick.trigger(kick_amp_decay_ms);
+            }
+
+            let mut freq = self.params.pitch.smoothed.next();
+            if glide_samples > 0 {
+                // The glide replaces the smoothing
+                freq = self.params.pitch.value();
+            }
+            if let Some(tracked) = tracked_pitch {
+                freq = tracked;
+            }
+            let freq = self.glide.next(freq, glide_samples, glide_curve);
+            let envelope = if midi_mode {
+                self.voices
+                    .iter_mut()
+                    .fold(0.0_f32, |acc, voice| acc.max(voice.adsr.level()))
+            } else if kick_mode {
+                self.kick.level()
+            } else if amp_env {
+                let open = gate_open[i];
+                if open && !self.amp_env_open {
+                    self.amp_env.note_on();
+                } else if !open && self.amp_env_open {
+                    self.amp_env.note_off(self.sample_rate);
+                }
+                self.amp_env_open = open;
+                self.amp_env.next(self.sample_rate)
+            } else {
+                input_envelope
+            };
+            let mods = self.mod_matrix.next(envelope, self.sample_rate);
+            let pitch_mod = 2.0_f32.powf(self.mod_matrix.modulation(ModDestination::Pitch, &mods));
+            // The noise only runs while drifting, so turning the drift on starts from zero
+            let drift_ratio = if drift > 0.0 {
+                cents_to_ratio(drift * self.drift_noise.next(DRIFT_RATE_HZ, self.sample_rate))
+            } else {
+                1.0
+            };
+            let tune = |freq: f32| {
+                let freq = freq * pitch_mod;
+                let freq = if quantize {
+                    quantize_pitch(freq, scale, root)
+                } else {
+                    freq
+                };
+                (freq * fine_tune * drift_ratio).min(max_gen_hz)
+            };
+            // The logarithmic smoother never quite reaches zero, treat -100 dB as a hard mute
+            let mut post = self.params.post_gain.smoothed.next();
+            if post < util::MINUS_INFINITY_GAIN {
+                post = 0.0;
+            }
+            let post = (post + self.mod_matrix.modulation(ModDestination::Gain, &mods)).max(0.0)
+                * amount_gain
+                * note_gate;
+            let drive = self.mod_matrix.modulation(ModDestination::Drive, &mods) + amount_drive;
+            // Recomputing the coefficients every sample isn't worth it for slow sweeps
+            if i == 0 {
+                let octaves = self.mod_matrix.modulation(ModDestination::Filter, &mods);
+                self.filter_cutoff =
+                    (cutoff_hz * 2.0_f32.powf(octaves)).clamp(cutoff_min_hz, cutoff_max_hz);
+                self.sub_lowpass.set_lowpass(
+                    self.filter_cutoff,
+                    filter::BUTTERWORTH_Q,
+                    self.sample_rate,
+                );
+            }
+
+            let sample = if midi_mode {
+                // Every voice carries its own note envelope
+                let bend = 2.0_f32.powf(self.pitch_bend * bend_range / 12.0);
+                let mut sum = 0.0;
+                for voice in self.voices.iter_mut() {
+                    let freq = tune(util::midi_note_to_freq(voice.note) * bend);
+                    let phase_inc = 2.0 * std::f32::consts::PI * freq / self.sample_rate;
+                    let level = voice.adsr.next(self.sample_rate);
+                    sum += level * render(advance(&mut voice.saws, freq), phase_inc);
+                }
+                sum
+            } else if kick_mode {
+                // The kick carries its own decay
+                self.kick.next(tune(freq), kick_pitch_decay_ms, self.sample_rate)
+            } else {
+                let freq = tune(freq);
+                let phase_inc = 2.0 * std::f32::consts::PI * freq / self.sample_rate;
+                let low = self.sync_lpf.process(mono[i]);
+                if zero_cross_sync && self.sync_last <= 0.0 && low > 0.0 {
+                    // The crossing happened between the two samples, start that far into the cycle
+                    let fraction = low / (low - self.sync_last);
+                    self.saws.set_phase(phase_inc * fraction);
+                }
+                self.sync_last = low;
+                let phases = advance(&mut self.saws, freq);
+                if phase_align {
+                    // Pull the saw a little toward the input every sample so it never jumps
+                    let error = self.phase_detector.next(mono[i], phases[0]);
+                    self.saws.shift(phase_align_rate * error);
+                }
+                let sample = render(phases, phase_inc);
+                if stretch > 0.0 {
+                    // Swap the harmonic fundamental saw for the stretched partials
+                    let stretched = additive_wave_stretch(
+                        &mut self.partials,
+                        &ADDITIVE_SAW,
+                        freq,
+                        stretch,
+                        self.sample_rate,
+                    );
+                    sample - osc(phases[0], phase_inc) + stretched
+                } else {
+                    sample
+                }
+            };
+            let sample = if amp_env { sample * envelope } else { sample };
+            let saturate = |sample: f32| {
+                let sample = if fold > 0.0 {
+                    shaper::wavefold(sample, fold)
+                } else {
+                    sample
+                };
+                if drive > 0.0 {
+                    shaper::saturate(sample * (1.0 + drive), saturation_type)
+                } else {
+                    sample
+                }
+            };
+            let oversampler = &mut self.oversampler;
+            let mut shape = |sample: f32| {
+                if oversample_shaper {
+                    oversampler.process(sample, saturate)
+                } else {
+                    saturate(sample)
+                }
+            };
+            // The shelves keep running without drive so they don't click when it kicks in
+            let sample = if emphasis_hz > 0.0 {
+                self.emphasis.process(sample, shape)
+            } else {
+                shape(sample)
+            };
+            let sample = if comb_delay > 0.0 {
+                self.comb.process(sample, comb_delay, comb_feedback)
+            } else {
+                sample
+            };
+            let sample = if formant_hz > 0.0 {
+                sample + self.formant.process(sample)
+            } else {
+                sample
+            };
+            let sample = if hi_shelf_gain_db != 0.0 {
+                self.hi_shelf.process(sample)
+            } else {
+                sample
+            };
+            let sample = if self.filter_cutoff < MAX_CUTOFF_HZ {
+                self.sub_lowpass.process(sample)
+            } else {
+                sample
+            };
+            saw.push(sample * post);
+            sub_level.push(envelope * post);
+            gain_stage = post;
+        }
+        self.voices.remove_finished();
+        self.current_gain.store(gain_stage.clamp(0.0, 1.0), Ordering::Relaxed);
+
+        // In MIDI and kick mode or with its own envelope the generator already carries its level
+        if midi_mode || kick_mode || amp_env {
+            curves = vec![vec![1.0; num_samples]];
+        }
+        let curve_for = |channel: usize| &curves[if curves.len() == 1 { 0 } else { channel }];
+
+        // The sub already contains the post gain, so matching it against the input RMS times the
+        // post gain only needs the ratio of the two levels
+        if self.params.auto_level.value() {
+            let post = self.params.post_gain.value();
+            for (i, sample) in saw.iter_mut().enumerate() {
+                let input_rms = self.auto_level_input.next(mono[i]);
+                let sub_rms = self.auto_level_sub.next(*sample * curve_for(0)[i]);
+                let gain = if sub_rms > 0.0 {
+                    (post * input_rms / sub_rms).min(MAX_AUTO_LEVEL_GAIN)
+                } else {
+                    0.0
+                };
+                *sample *= gain;
+                sub_level[i] *= gain;
+            }
+        }
+
+        // Fade the sub in after a reset so activating the plugin doesn't pop
+        let fade_samples = self.params.fade_in_ms.value() * 0.001 * self.sample_rate;
+        for (sample, level) in saw.iter_mut().zip(&mut sub_level) {
+            if (self.fade_in_pos as f32) >= fade_samples {
+                break;
+            }
+            let gain = self.fade_in_pos as f32 / fade_samples;
+            *sample *= gain;
+            *level *= gain;
+            self.fade_in_pos += 1;
+        }
+
+        // Side signal for the first two channels, the filters always run to stay current
+        let decorrelate = self.params.decorrelate.value();
+        let side: Vec<f32> = saw
+            .iter()
+            .map(|&sample| {
+                let wet = self.decorrelator.iter_mut().fold(sample, |s, f| f.process(s));
+                decorrelate * wet
+            })
+            .collect();
+
+        let trim_for = |channel: usize| {
+            self.channel_trims
+                .get(channel)
+                .map_or(1.0, |&trim_db| util::db_to_gain(trim_db))
+        };
+        let out_ch = self.params.out_channel.value();
+        if out_ch == 0 {
+            let stereo = slices.len() > 1;
+            let haas_delay = (self.params.haas_ms.value() * 0.001 * self.sample_rate) as usize;
+            for (idx, channel) in slices.iter_mut().enumerate() {
+                let curve = curve_for(idx);
+                let trim = trim_for(idx);
+                let side_sign = match idx {
+                    0 if stereo => 1.0,
+                    1 => -1.0,
+                    _ => 0.0,
+                };
+                for (i, sample) in channel.iter_mut().enumerate() {
+                    let sub = (saw[i] + side_sign * side[i]) * curve[i] * trim;
+                    // The delay line always runs on the right channel to stay current
+                    *sample += if idx == 1 {
+                        self.haas.process(sub, haas_delay)
+                    } else {
+                        sub
+                    };
+                }
+            }
+        } else {
+            let idx = (out_ch - 1) as usize;
+            if idx < slices.len() {
+                let curve = curve_for(idx);
+                let trim = trim_for(idx);
+                for (i, sample) in slices[idx].iter_mut().enumerate() {
+                    *sample = saw[i] * curve[i] * trim;
+                }
+            }
+        }
+
+        // Mono maker, everything below the crossover is replaced by the mono sum of the low bands
+        let mono_below = self.params.mono_below_hz.value();
+        if mono_below > 0.0 && slices.len() > 1 {
+            for crossover in &mut self.crossovers {
+                crossover.set_frequency(mono_below, self.sample_rate);
+            }
+            let num_bands = slices.len().min(self.crossovers.len());
+            for i in 0..num_samples {
+                let mut low_sum = 0.0;
+                for (channel, crossover) in slices.iter_mut().zip(&mut self.crossovers) {
+                    let (low, high) = crossover.split(channel[i]);
+                    low_sum += low;
+                    channel[i] = high;
+                }
+                let low_mono = low_sum / num_bands as f32;
+                for channel in slices.iter_mut().take(num_bands) {
+                    channel[i] += low_mono;
+                }
+            }
+        }
+
+        if self.params.dc_block.value() {
+            for (channel, blocker) in slices.iter_mut().zip(&mut self.dc_blockers) {
+                for sample in channel.iter_mut() {
+                    *sample = blocker.process(*sample);
+                }
+            }
+        }
+
+        let compressor_ratio = self.params.compressor_ratio.value();
+        if compressor_ratio > 1.0 {
+            self.compressor.process(
+                slices,
+                self.params.compressor_threshold_db.value(),
+                compressor_ratio,
+                self.params.stereo_link.value(),
+            );
+        }
+
+        if self.params.limiter.value() {
+            self.limiter.set_lookahead(self.latency_samples() as usize);
+            self.limiter.set_knee_db(self.params.limiter_knee_db.value());
+            let release_ms = if self.params.limiter_zero_latency.value() {
+                LIMITER_ZERO_LATENCY_RELEASE_MS
+            } else {
+                LIMITER_RELEASE_MS
+            };
+            self.limiter.process(
+                slices,
+                util::db_to_gain(self.params.limiter_ceiling_db.value()),
+                smoothing_coeff(release_ms, self.sample_rate),
+            );
+        }
+
+        if self.params.dither.value() {
+            for channel in slices.iter_mut() {
+                for sample in channel.iter_mut() {
+                    *sample += self.rng.next_tpdf() * DITHER_LSB;
+                }
+            }
+        }
+
+        let correlation = if let [left, right, ..] = slices {
+            for (&l, &r) in left.iter().zip(right.iter()) {
+                self.correlation_meter.next(l, r);
+            }
+            self.correlation_meter.value()
+        } else {
+            1.0
+        };
+        self.output_correlation.store(correlation, Ordering::Relaxed);
+
+        sub_level
+    }
+}
+
+impl Plugin for SubrouRs {
+    const NAME: &'static str = "Subrou Rs";
+    const VENDOR: &'static str = "Daishi Suzuki";
+    const URL: &'static str = env!("CARGO_PKG_HOMEPAGE");
+    const EMAIL: &'static str = "zukky.rikugame@gmail.com";
+
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    // The first audio IO layout is used as the default. The other layouts may be selected either
+    // explicitly or automatically by the host or the user depending on the plugin API/backend.
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
+        main_input_channels: NonZeroU32::new(2),
+        main_output_channels: NonZeroU32::new(2),
+
+        // Optional second input to generate the sub from, see `source`
+        aux_input_ports: &[new_nonzero_u32(2)],
+        // Level of the generated sub as an audio rate control signal, for sidechaining
+        aux_output_ports: &[new_nonzero_u32(1)],
+
+        // Individual ports and the layout as a whole can be named here. By default these names
+        // are generated as needed. This layout will be called 'Stereo', while a layout with
+        // only one input and output channel would be called 'Mono'.
+        names: PortNames {
+            aux_inputs: &["Source"],
+            aux_outputs: &["Sub Level"],
+            ..PortNames::const_default()
+        },
+    }];
+
+
+    // Pitch bend is only sent with `MidiCCs`
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
+    const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
+
+    const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+
+    // If the plugin can send or receive SysEx messages, it can define a type to wrap around those
+    // messages here. The type implements the `SysExMessage` trait, which allows conversion to and
+    // from plain byte buffers.
+    type SysExMessage = ();
+    // More advanced plugins can use this to run expensive background tasks. See the field's
+    // documentation for more information. `()` means that the plugin does not have any background
+    // tasks.
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn initialize(
+        &mut self,
+        audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> bool {
+        // Resize buffers and perform other potentially expensive initialization operations here.
+        // The `reset()` function is always called right after this function. You can remove this
+        // function if you do not need it.
+        let num_channels = audio_io_layout
+            .main_output_channels
+            .map(NonZeroU32::get)
+            .unwrap_or(0) as usize;
+        // Only the channel counts matter here, `update_sample_rate` sets up everything else
+        self.crossovers = vec![Crossover::new(100.0, self.sample_rate); num_channels];
+        self.followers =
+            vec![EnvelopeFollower::new(10.0, 10.0, self.sample_rate); num_channels.max(1)];
+        self.gates = vec![GateState::default(); num_channels.max(1)];
+        self.curve_smoothers = vec![0.0; num_channels.max(1)];
+        self.true_peak_meters = vec![TruePeakMeter::default(); num_channels];
+        self.key_filters = vec![KeyFilter::default(); num_channels.max(1)];
+        self.dc_blockers =
+            vec![DcBlocker::new(filter::DC_BLOCK_HZ, self.sample_rate); num_channels];
+        self.dry_scratch = vec![vec![0.0; buffer_config.max_buffer_size as usize]; num_channels];
+        self.limiter = Limiter::new(num_channels, 0);
+        self.update_sample_rate(buffer_config.sample_rate);
+        self.latency = self.latency_samples();
+        context.set_latency_samples(self.latency);
+        true
+    }
+
+    fn reset(&mut self) {
+        // Reset buffers and envelopes here. This can be called from the audio thread and may not
+        // allocate. You can remove this function if you do not need it.
+        for follower in &mut self.followers {
+            follower.reset();
+        }
+        self.crossfeed_hpf.reset();
+        self.crossfeed_follower.reset();
+        self.auto_level_input.reset();
+        self.bypass_match_dry.reset();
+        self.bypass_match_wet.reset();
+        self.auto_level_sub.reset();
+        self.gates.fill(GateState::default());
+        self.curve_smoothers.fill(0.0);
+        self.saws.reset();
+        self.partials = [Oscillator::default(); ADDITIVE_SAW.len()];
+        self.sync_lpf.reset();
+        self.sync_last = 0.0;
+        self.phase_detector.reset();
+        for crossover in &mut self.crossovers {
+            crossover.reset();
+        }
+        self.voices.reset();
+        self.pitch_bend = 0.0;
+        self.transients.reset();
+        self.kick.reset();
+        self.glide.reset();
+        self.amp_env.reset();
+        self.amp_env_open = false;
+        self.pitch_tracker.reset();
+        self.detected_pitch.store(0.0, Ordering::Relaxed);
+        self.correlation_meter.reset();
+        self.output_correlation.store(0.0, Ordering::Relaxed);
+        for meter in &mut self.true_peak_meters {
+            meter.reset();
+        }
+        self.true_peak.store(0.0, Ordering::Relaxed);
+        self.current_gain.store(0.0, Ordering::Relaxed);
+        self.reseed();
+        for blocker in &mut self.dc_blockers {
+            blocker.reset();
+        }
+        self.compressor.reset();
+        self.limiter.reset();
+        self.emphasis.reset();
+        self.oversampler.reset();
+        self.comb.reset();
+        self.formant.reset();
+        self.hi_shelf.reset();
+        self.sub_lowpass.reset();
+        for key_filter in &mut self.key_filters {
+            key_filter.reset();
+        }
+        for filter in &mut self.decorrelator {
+            filter.reset();
+        }
+        self.haas.reset();
+        self.mod_matrix.reset();
+        self.custom_curve_pos = 0;
+        self.fade_in_pos = 0;
+        // Nothing to fade from after a reset
+        self.bypass_mix = if self.params.bypass.value() { 1.0 } else { 0.0 };
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let num_samples = buffer.samples();
+        if num_samples == 0 {
+            return ProcessStatus::Normal;
+        }
+
+        let latency = self.latency_samples();
+        if latency != self.latency {
+            self.latency = latency;
+            context.set_latency_samples(latency);
+        }
+
+        if self.mod_matrix.lfo_bars.is_some() {
+            let transport = context.transport();
+            if let Some(tempo) = transport.tempo {
+                self.mod_matrix.sync_lfo(
+                    tempo,
+                    transport.time_sig_numerator.unwrap_or(4),
+                    transport.time_sig_denominator.unwrap_or(4),
+                );
+            }
+        }
+
+        if self.params.source.value() == InputSource::Aux {
+            if let Some(aux_input) = aux.inputs.first_mut() {
+                if aux_input.samples() == num_samples {
+                    self.aux_mono = detector_signal(
+                        aux_input.as_slice(),
+                        self.params.detector_mono_mode.value(),
+                        &DetectorChannels::default(),
+                    );
+                }
+            }
+        }
+
+        let sub_level = self.process_channels(buffer.as_slice(), || context.next_event());
+        for aux_output in aux.outputs.iter_mut() {
+            for channel in aux_output.as_slice() {
+                channel.copy_from_slice(&sub_level);
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl ClapPlugin for SubrouRs {
+    const CLAP_ID: &'static str = "com.zukky.subrou-rs";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("SubBaseMaker");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+
+    // Don't forget to change these features
+    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Stereo];
+}
+
+impl Vst3Plugin for SubrouRs {
+    const VST3_CLASS_ID: [u8; 16] = *b"Subrou!!!!!!!!!!";
+
+    // And also don't forget to change these categories
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Dynamics];
+}
+
+nih_export_clap!(SubrouRs);
+nih_export_vst3!(SubrouRs);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[derive(Default)]
+    struct DummyContext {
+        events: VecDeque<PluginNoteEvent<SubrouRs>>,
+    }
+
+    impl ProcessContext<SubrouRs> for DummyContext {
+        fn plugin_api(&self) -> PluginApi { PluginApi::Vst3 }
+        fn execute_background(&self, _task: ()) {}
+        fn execute_gui(&self, _task: ()) {}
+        fn transport(&self) -> &Transport { unreachable!("transport unused") }
+        fn next_event(&mut self) -> Option<PluginNoteEvent<SubrouRs>> { self.events.pop_front() }
+        fn send_event(&mut self, _event: PluginNoteEvent<SubrouRs>) {}
+        fn set_latency_samples(&self, _samples: u32) {}
+        fn set_current_voice_capacity(&self, _capacity: u32) {}
+    }
+
+    struct DummyInitContext;
+
+    impl InitContext<SubrouRs> for DummyInitContext {
+        fn plugin_api(&self) -> PluginApi { PluginApi::Vst3 }
+        fn execute(&self, _task: ()) {}
+        fn set_latency_samples(&self, _samples: u32) {}
+        fn set_current_voice_capacity(&self, _capacity: u32) {}
+    }
+
+    /// Records the values parameters are set to through the GUI context.
+    #[derive(Default)]
+    struct RecordingGuiContext {
+        values: std::sync::Mutex<Vec<f32>>,
+        open_gestures: std::sync::atomic::AtomicUsize,
+    }
+
+    impl GuiContext for RecordingGuiContext {
+        fn plugin_api(&self) -> PluginApi { PluginApi::Vst3 }
+        fn request_resize(&self) -> bool { false }
+        unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {
+            self.open_gestures.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe fn raw_set_parameter_normalized(&self, _param: ParamPtr, normalized: f32) {
+            self.values.lock().unwrap().push(normalized);
+        }
+        unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {
+            self.open_gestures.fetch_sub(1, Ordering::Relaxed);
+        }
+        fn get_state(&self) -> PluginState { unreachable!("state unused") }
+        fn set_state(&self, _state: PluginState) {}
+    }
+
+    /// Only the host can set parameter values, so tests move a parameter by replacing it with one
+    /// that has the same name, range, unit and smoother and the new value.
+    trait SetValue: Param {
+        fn set(&mut self, value: Self::Plain);
+    }
+
+    impl SetValue for FloatParam {
+        fn set(&mut self, value: f32) {
+            let range = self.range();
+            let snapped = range.unnormalize(range.normalize(value));
+            assert!((snapped - value).abs() < 1e-3, "{value} is outside of {}", self.name());
+            *self = FloatParam::new(self.name(), value, range)
+                .with_unit(self.unit())
+                .with_smoother(self.smoothed.style.clone());
+        }
+    }
+
+    impl SetValue for IntParam {
+        fn set(&mut self, value: i32) {
+            let range = self.range();
+            assert_eq!(range.unnormalize(range.normalize(value)), value, "{}", self.name());
+            *self = IntParam::new(self.name(), value, range)
+                .with_unit(self.unit())
+                .with_smoother(self.smoothed.style.clone());
+        }
+    }
+
+    impl SetValue for BoolParam {
+        fn set(&mut self, value: bool) {
+            *self = BoolParam::new(self.name(), value);
+        }
+    }
+
+    impl<T: Enum + PartialEq + 'static> SetValue for EnumParam<T> {
+        fn set(&mut self, value: T) {
+            *self = EnumParam::new(self.name(), value);
+        }
+    }
+
+    /// The default plugin with its parameters adjusted by `set` and the smoothers at their
+    /// targets.
+    fn plugin_with(set: impl FnOnce(&mut SubrouRsParams)) -> SubrouRs {
+        let mut params = SubrouRsParams::default();
+        set(&mut params);
+        let mut plugin = SubrouRs { params: Arc::new(params), ..SubrouRs::default() };
+        plugin.reset_smoothers();
+        plugin
+    }
+
+    #[test]
+    fn test_process_silence() {
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        let mut left = vec![0.0_f32; 64];
+        let mut right = vec![0.0_f32; 64];
+        let mut buffer = Buffer::default();
+        unsafe { buffer.set_slices(64, |out| *out = vec![&mut left, &mut right]) };
+        let mut aux = AuxiliaryBuffers { inputs: &mut [], outputs: &mut [] };
+        let mut ctx = DummyContext::default();
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
+        for ch in buffer.as_slice() {
+            assert!(ch.iter().all(|&s| s == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_process_generates_audio() {
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        let mut left = vec![1.0_f32; 64];
+        let mut right = vec![1.0_f32; 64];
+        let mut buffer = Buffer::default();
+        unsafe { buffer.set_slices(64, |out| *out = vec![&mut left, &mut right]) };
+        let mut aux = AuxiliaryBuffers { inputs: &mut [], outputs: &mut [] };
+        let mut ctx = DummyContext::default();
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
+        let slices = buffer.as_slice();
+        assert!(slices[0].iter().any(|&s| s != 1.0));
+        assert_eq!(slices[0], slices[1]);
+    }
+
+    /// Set the plugin up the way a host would for `num_channels` outputs.
+    fn initialize(plugin: &mut SubrouRs, num_channels: u32, sample_rate: f32) {
+        let layout = AudioIOLayout {
+            main_output_channels: NonZeroU32::new(num_channels),
+            ..AudioIOLayout::const_default()
+        };
+        let config = BufferConfig {
+            sample_rate,
+            min_buffer_size: None,
+            max_buffer_size: 4096,
+            process_mode: ProcessMode::Realtime,
+        };
+        assert!(plugin.initialize(&layout, &config, &mut DummyInitContext));
+    }
+
+    fn process_stereo(plugin: &mut SubrouRs, left: &mut [f32], right: &mut [f32]) {
+        process_stereo_with_events(plugin, left, right, Vec::new());
+    }
+
+    fn process_stereo_with_events(
+        plugin: &mut SubrouRs,
+        left: &mut [f32],
+        right: &mut [f32],
+        events: Vec<PluginNoteEvent<SubrouRs>>,
+    ) {
+        let num_samples = left.len();
+        let mut buffer = Buffer::default();
+        unsafe { buffer.set_slices(num_samples, |out| *out = vec![left, right]) };
+        let mut aux = AuxiliaryBuffers { inputs: &mut [], outputs: &mut [] };
+        let mut ctx = DummyContext { events: events.into() };
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
+    }
+
+    #[test]
+    fn test_process_osc_quality_modes() {
+        // Energy above Nyquist/2 of each mode as `process` dispatches it, at the highest pitch
+        let high_energy = |quality: OscQuality| {
+            let mut plugin = plugin_with(|params| {
+                params.osc_quality.set(quality);
+                params.pitch.set(2000.0);
+                params.dry_gain.set(0.0);
+            });
+            let output = plugin.process_in_blocks(&[1.0; 8820], 512);
+            let total = test_util::band_energy(&output[4410..], 44100.0, 0.0, 22050.0);
+            assert!(total > 0.1);
+            test_util::band_energy(&output[4410..], 44100.0, 11025.0, 22050.0)
+        };
+        let additive = high_energy(OscQuality::Additive);
+        let band_limited = high_energy(OscQuality::BandLimited);
+        let naive = high_energy(OscQuality::Naive);
+        // The additive saw keeps every harmonic up to Nyquist at full level, the PolyBLEP saw
+        // rolls them off towards the top, and the naive ramp adds its aliases on top
+        assert!(band_limited < additive);
+        assert!(band_limited < 0.5 * naive);
+    }
+
+    #[test]
+    fn test_process_octave_interval_adds_energy() {
+        let mut magnitudes = Vec::new();
+        for octave_level in [0.0, 1.0] {
+            let mut plugin = plugin_with(|params| params.octave_level.set(octave_level));
+            let mut left = vec![1.0_f32; 4410];
+            let mut right = vec![1.0_f32; 4410];
+            process_stereo(&mut plugin, &mut left, &mut right);
+            magnitudes.push(test_util::magnitude_at(&left, 44100.0, 880.0));
+        }
+        assert!(magnitudes[1] > magnitudes[0] * 2.0);
+    }
+
+    #[test]
+    fn test_process_mono_below() {
+        let mut plugin = plugin_with(|params| params.mono_below_hz.set(200.0));
+
+        // A 40 Hz tone with the two channels 90 degrees apart
+        let phase = |i: usize| 2.0 * std::f32::consts::PI * 40.0 * i as f32 / 44100.0;
+        let mut left: Vec<f32> = (0..8820).map(|i| 0.5 * phase(i).sin()).collect();
+        let mut right: Vec<f32> = (0..8820).map(|i| 0.5 * phase(i).cos()).collect();
+        process_stereo(&mut plugin, &mut left, &mut right);
+        for (l, r) in left[4410..].iter().zip(&right[4410..]) {
+            assert!((l - r).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_process_dry_gain_mute() {
+        let input: Vec<f32> = (0..512).map(|i| (i as f32 * 0.05).sin()).collect();
+
+        let mut plugin = plugin_with(|params| params.dc_block.set(false));
+        let mut left = input.clone();
+        let mut right = input.clone();
+        process_stereo(&mut plugin, &mut left, &mut right);
+        let sub: Vec<f32> = left.iter().zip(&input).map(|(o, i)| o - i).collect();
+
+        let mut plugin = plugin_with(|params| {
+            params.dc_block.set(false);
+            params.dry_gain.set(0.0);
+        });
+        let mut left = input.clone();
+        let mut right = input.clone();
+        process_stereo(&mut plugin, &mut left, &mut right);
+        for (out, expected) in left.iter().zip(&sub) {
+            assert!((out - expected).abs() < 1e-6);
+        }
+        assert!(left.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn test_process_midi_note_off_releases() {
+        let mut plugin = plugin_with(|params| {
+            params.dc_block.set(false);
+            params.midi_mode.set(true);
+        });
+
+        // 100 ms release is 4410 samples
+        let events = vec![
+            NoteEvent::NoteOn { timing: 0, voice_id: None, channel: 0, note: 33, velocity: 1.0 },
+            NoteEvent::NoteOff { timing: 4410, voice_id: None, channel: 0, note: 33, velocity: 0.0 },
+        ];
+        let mut left = vec![0.0_f32; 13230];
+        let mut right = vec![0.0_f32; 13230];
+        process_stereo_with_events(&mut plugin, &mut left, &mut right, events);
+
+        let peak = |range: std::ops::Range<usize>| {
+            left[range].iter().fold(0.0_f32, |acc, &s| acc.max(s.abs()))
+        };
+        let held = peak(2205..4410);
+        let early_release = peak(4410..5512);
+        let late_release = peak(7717..8820);
+        assert!(held > 0.5);
+        assert!(early_release > 0.5 * held && early_release <= held);
+        assert!(late_release > 0.0 && late_release < early_release);
+        assert_eq!(peak(8830..13230), 0.0);
+    }
+
+    #[test]
+    fn test_process_link_channels() {
+        for link in [true, false] {
+            let mut plugin = plugin_with(|params| {
+                params.dc_block.set(false);
+                params.link_channels.set(link);
+            });
+            let mut left = vec![1.0_f32; 512];
+            let mut right = vec![0.25_f32; 512];
+            process_stereo(&mut plugin, &mut left, &mut right);
+
+            let left_sub: Vec<f32> = left.iter().map(|s| s - 1.0).collect();
+            let right_sub: Vec<f32> = right.iter().map(|s| s - 0.25).collect();
+            let identical = left_sub
+                .iter()
+                .zip(&right_sub)
+                .all(|(l, r)| (l - r).abs() < 1e-6);
+            assert_eq!(identical, link);
+        }
+    }
+
+    #[test]
+    fn test_process_mono_silence() {
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        let output = plugin.process_mono(&[0.0; 256]);
+        assert_eq!(output, vec![0.0; 256]);
+    }
+
+    #[test]
+    fn test_process_mono_envelope_shaped_sub() {
+        let mut plugin = plugin_with(|params| params.dc_block.set(false));
+        let input: Vec<f32> = (0..1024)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let output = plugin.process_mono(&input);
+
+        // The sub is the default 440 Hz saw scaled by the gated envelope of the input
+        let terms = max_safe_terms(440.0, 44100.0);
+        let mut gate = GateState::default();
+        let (open, close) = (util::db_to_gain(-60.0), util::db_to_gain(-70.0));
+        let mut osc = Oscillator::default();
+        let envelope = envelope_follower(&input, 10.0, 10.0, 44100.0);
+        for ((out, dry), env) in output.iter().zip(&input).zip(&envelope) {
+            let gain = if gate.update(*env, open, close) { *env } else { 0.0 };
+            let expected = saw_wave(osc.next(440.0, 44100.0), terms) * gain;
+            assert!((out - dry - expected).abs() < 1e-5);
+        }
+        assert!(output.iter().zip(&input).any(|(out, dry)| out != dry));
+    }
+
+    #[test]
+    fn test_process_dither() {
+        let input: Vec<f32> = (0..4096).map(|i| 0.001 * (i as f32 * 0.01).sin()).collect();
+        let render = |dither: bool| {
+            let mut plugin = plugin_with(|params| params.dither.set(dither));
+            plugin.process_mono(&input)
+        };
+
+        // Without dither the output is bit exact between runs
+        let plain = render(false);
+        assert_eq!(plain, render(false));
+
+        let dithered = render(true);
+        let noise: Vec<f32> = dithered.iter().zip(&plain).map(|(d, p)| d - p).collect();
+        let mean = noise.iter().sum::<f32>() / noise.len() as f32;
+        assert!(noise.iter().any(|&n| n != 0.0));
+        assert!(noise.iter().all(|&n| n.abs() < 2.0 * DITHER_LSB));
+        assert!(mean.abs() < 0.1 * DITHER_LSB);
+    }
+
+    #[test]
+    fn test_process_block_size_independent() {
+        let input: Vec<f32> = (0..4096)
+            .map(|i| {
+                (2.0 * std::f32::consts::PI * 60.0 * i as f32 / 44100.0).sin() * (i as f32 / 4096.0)
+            })
+            .collect();
+        let render = |block: usize| {
+            let mut plugin = SubrouRs::default();
+            plugin.reset_smoothers();
+            plugin.process_in_blocks(&input, block)
+        };
+        let small = render(64);
+        assert_eq!(small, render(128));
+        assert_eq!(small, render(input.len()));
+    }
+
+    #[test]
+    fn test_mod_matrix_envelope_to_pitch() {
+        // A quiet and a loud 40 Hz sine, the routed envelope should raise the generated pitch
+        let sample_rate = 44100.0;
+        let render = |level: f32| {
+            let mut plugin = plugin_with(|params| params.dry_gain.set(0.0));
+            plugin.mod_matrix_mut().add_route(ModRoute::new(
+                ModSource::Envelope,
+                ModDestination::Pitch,
+                2.0,
+            ));
+            let input: Vec<f32> = (0..8820)
+                .map(|i| {
+                    level * (2.0 * std::f32::consts::PI * 40.0 * i as f32 / sample_rate).sin()
+                })
+                .collect();
+            plugin.process_mono(&input)
+        };
+        let crossings = |out: &[f32]| {
+            out.windows(2)
+                .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+                .count()
+        };
+        let quiet = crossings(&render(0.05));
+        let loud = crossings(&render(0.5));
+        assert!(loud > quiet * 5 / 4, "{loud} vs {quiet}");
+    }
+
+    #[test]
+    fn test_reset_smoothers_applies_values_immediately() {
+        let mut plugin = plugin_with(|params| params.dry_gain.set(0.25));
+        // The saw starts at zero, so the first sample is only the scaled input
+        let output = plugin.process_mono(&[1.0; 16]);
+        assert_eq!(output[0], 0.25);
+    }
+
+    #[test]
+    fn test_process_listen_outputs_detector_signal() {
+        let mut plugin = plugin_with(|params| {
+            params.listen.set(true);
+            params.key_low_hz.set(40.0);
+            params.key_high_hz.set(120.0);
+            params.detector_gain_db.set(6.0);
+        });
+        let mut left: Vec<f32> = (0..512).map(|i| (i as f32 * 0.05).sin()).collect();
+        let mut right: Vec<f32> = (0..512).map(|i| 0.5 * (i as f32 * 0.02).cos()).collect();
+        let mono = detector_signal(
+            &[left.as_mut_slice(), right.as_mut_slice()],
+            DetectorMonoMode::Average,
+            &DetectorChannels::default(),
+        );
+        // The key band and the pre-gain are part of what the detector hears
+        let mut key_filter = KeyFilter::default();
+        key_filter.set_band(Some(40.0), Some(120.0), plugin.sample_rate);
+        let gain = util::db_to_gain(6.0);
+        let detector: Vec<f32> = mono.iter().map(|&s| key_filter.process(s) * gain).collect();
+        assert!(detector.iter().zip(&mono).any(|(d, m)| (d - m).abs() > 0.1));
+        process_stereo(&mut plugin, &mut left, &mut right);
+        assert_eq!(left, detector);
+        assert_eq!(right, detector);
+    }
+
+    #[test]
+    fn test_process_min_post_gain_mutes_sub() {
+        let mut plugin = plugin_with(|params| {
+            params.dc_block.set(false);
+            params.post_gain.set(0.0);
+            params.dry_gain.set(0.0);
+        });
+        // Fade the post gain out from unity to its minimum. Once the ramp passes -100 dB the sub
+        // should be gone entirely, not just very quiet
+        plugin.params.post_gain.smoothed.reset(1.0);
+        plugin.params.post_gain.smoothed.set_target(44100.0, 0.0);
+        let input: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.01).sin()).collect();
+        let output = plugin.process_mono(&input);
+        assert!(output[..300].iter().any(|&s| s != 0.0));
+        assert!(output[330..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_process_midi_polyphony() {
+        let mut plugin = plugin_with(|params| params.midi_mode.set(true));
+
+        // A2 and E3 pressed together, both should keep sounding
+        let events = vec![
+            NoteEvent::NoteOn { timing: 0, voice_id: None, channel: 0, note: 45, velocity: 1.0 },
+            NoteEvent::NoteOn { timing: 0, voice_id: None, channel: 0, note: 52, velocity: 1.0 },
+        ];
+        let mut left = vec![0.0_f32; 8820];
+        let mut right = vec![0.0_f32; 8820];
+        process_stereo_with_events(&mut plugin, &mut left, &mut right, events);
+
+        let sample_rate = 44100.0;
+        let a2 = test_util::magnitude_at(&left[4410..], sample_rate, util::midi_note_to_freq(45));
+        let e3 = test_util::magnitude_at(&left[4410..], sample_rate, util::midi_note_to_freq(52));
+        assert!(a2 > 0.3 && e3 > 0.3);
+    }
+
+    #[test]
+    fn test_process_detected_pitch() {
+        let mut plugin = plugin_with(|params| params.track_pitch.set(true));
+        assert_eq!(plugin.detected_pitch(), None);
+
+        let input: Vec<f32> = (0..8192)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 220.0 * i as f32 / 44100.0).sin())
+            .collect();
+        plugin.process_in_blocks(&input, 512);
+        let detected = plugin.detected_pitch().unwrap();
+        assert!((detected - 220.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_process_crossfeed_follows_micro_dynamics() {
+        // A 1 kHz tone with fast 80 Hz tremolo, which the regular follower mostly smooths away
+        let sample_rate = 44100.0;
+        let tremolo: Vec<f32> = (0..22050)
+            .map(|i| 0.5 + 0.5 * (2.0 * std::f32::consts::PI * 80.0 * i as f32 / sample_rate).sin())
+            .collect();
+        let input: Vec<f32> = tremolo
+            .iter()
+            .enumerate()
+            .map(|(i, t)| t * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate).sin())
+            .collect();
+
+        let correlation = |crossfeed: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.dry_gain.set(0.0);
+                params.crossfeed.set(crossfeed);
+            });
+            let output = plugin.process_mono(&input);
+            let level = envelope_follower(&output, 2.0, 2.0, sample_rate);
+            test_util::correlation(&level[4410..], &tremolo[4410..])
+        };
+        assert!(correlation(1.0) > correlation(0.0) + 0.1);
+    }
+
+    #[test]
+    fn test_update_sample_rate_keeps_pitch() {
+        let mut plugin = plugin_with(|params| params.dry_gain.set(0.0));
+        for sample_rate in [44100.0, 96000.0] {
+            plugin.update_sample_rate(sample_rate);
+            plugin.reset();
+            plugin.reset_smoothers();
+            let input: Vec<f32> = (0..sample_rate as usize / 5)
+                .map(|i| (2.0 * std::f32::consts::PI * 50.0 * i as f32 / sample_rate).sin())
+                .collect();
+            let output = plugin.process_mono(&input);
+            // The default pitch is 440 Hz, a stale rate would put it at 202 or 958 Hz instead
+            let tone = test_util::magnitude_at(&output, sample_rate, 440.0);
+            let stale = test_util::magnitude_at(&output, sample_rate, 440.0 * 44100.0 / 96000.0)
+                .max(test_util::magnitude_at(&output, sample_rate, 440.0 * 96000.0 / 44100.0));
+            assert!(tone > 0.1 && tone > 5.0 * stale);
+        }
+    }
+
+    #[test]
+    fn test_process_phase_align_reinforces_source() {
+        let sample_rate = 44100.0;
+        let input: Vec<f32> = (0..8192)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 110.0 * i as f32 / sample_rate).sin())
+            .collect();
+        let render = |phase_align: bool| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(110.0);
+                params.phase_align.set(phase_align);
+            });
+            // Start the saw in antiphase with the input
+            plugin.saws.set_phase(std::f32::consts::PI);
+            let output = plugin.process_in_blocks(&input, 512);
+            test_util::magnitude_at(&output[4096..], sample_rate, 110.0)
+        };
+        assert!(render(true) > render(false) + 0.1);
+    }
+
+    #[test]
+    fn test_process_phase_align_block_size_independent() {
+        let input: Vec<f32> = (0..8192)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 110.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let render = |block: usize| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(110.0);
+                params.phase_align.set(true);
+            });
+            plugin.saws.set_phase(std::f32::consts::PI);
+            plugin.process_in_blocks(&input, block)
+        };
+        let small = render(64);
+        assert_eq!(small, render(512));
+        assert_eq!(small, render(input.len()));
+    }
+
+    #[test]
+    fn test_process_freeze_holds_envelope() {
+        let mut plugin = plugin_with(|params| params.dry_gain.set(0.0));
+        let loud: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.01).sin()).collect();
+        plugin.process_mono(&loud);
+        let level = plugin.followers[0].value();
+        assert!(level > 0.5);
+
+        // Engage the freeze while keeping all processing state
+        let mut params = SubrouRsParams::default();
+        params.dry_gain.set(0.0);
+        params.freeze.set(true);
+        plugin.params = Arc::new(params);
+        plugin.reset_smoothers();
+        let output = plugin.process_mono(&[0.0; 44100]);
+        assert_eq!(plugin.followers[0].value(), level);
+        let peak = |out: &[f32]| out.iter().fold(0.0_f32, |acc, &s| acc.max(s.abs()));
+        assert!((peak(&output[..4410]) - peak(&output[39690..])).abs() < 0.01);
+        assert!(peak(&output[39690..]) > 0.4);
+    }
+
+    #[test]
+    fn test_process_aux_sub_level() {
+        let mut plugin = plugin_with(|params| {
+            params.dry_gain.set(0.0);
+            params.dc_block.set(false);
+        });
+
+        // A decaying 60 Hz tone
+        let input: Vec<f32> = (0..8820)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                (-t * 10.0).exp() * (2.0 * std::f32::consts::PI * 60.0 * t).sin()
+            })
+            .collect();
+        let mut left = input.clone();
+        let mut right = input.clone();
+        let mut level = vec![0.0_f32; input.len()];
+        {
+            let mut buffer = Buffer::default();
+            let mut aux_buffer = Buffer::default();
+            unsafe {
+                buffer.set_slices(input.len(), |out| {
+                    *out = vec![left.as_mut_slice(), right.as_mut_slice()]
+                });
+                aux_buffer.set_slices(input.len(), |out| *out = vec![level.as_mut_slice()]);
+            }
+            let mut aux_outputs = [aux_buffer];
+            let mut aux = AuxiliaryBuffers { inputs: &mut [], outputs: &mut aux_outputs };
+            plugin.process(&mut buffer, &mut aux, &mut DummyContext::default());
+        }
+
+        // The level is the envelope the sub is scaled by, so together with the saw's peak it
+        // bounds the output, and it follows the input's envelope once the gate has opened. The DC
+        // blocker is off since it lets the saw's edge overshoot a little.
+        let envelope = envelope_follower(&input, 10.0, 10.0, 44100.0);
+        let terms = max_safe_terms(440.0, 44100.0);
+        let peak = (0..4096)
+            .map(|i| saw_wave(2.0 * std::f32::consts::PI * i as f32 / 4096.0, terms).abs())
+            .fold(0.0_f32, f32::max);
+        for i in 0..input.len() {
+            assert!(left[i].abs() <= peak * level[i] + 1e-4);
+            if i >= 441 {
+                assert!((level[i] - envelope[i]).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_detector_mono_mode_out_of_phase() {
+        for (mode, audible) in [(DetectorMonoMode::Average, false), (DetectorMonoMode::Max, true)] {
+            let mut plugin = plugin_with(|params| params.detector_mono_mode.set(mode));
+            let mut left: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.01).sin()).collect();
+            let mut right: Vec<f32> = left.iter().map(|s| -s).collect();
+            process_stereo(&mut plugin, &mut left, &mut right);
+            let envelope = plugin.followers[0].value();
+            if audible {
+                assert!(envelope > 0.5);
+            } else {
+                assert!(envelope < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_auto_level_tracks_input() {
+        let mut plugin = plugin_with(|params| {
+            params.dry_gain.set(0.0);
+            params.auto_level.set(true);
+        });
+
+        // One second of a quiet tone followed by one second of the same tone 20 dB louder
+        let input: Vec<f32> = (0..88200)
+            .map(|i| {
+                let level = if i < 44100 { 0.05 } else { 0.5 };
+                level * (2.0 * std::f32::consts::PI * 60.0 * i as f32 / 44100.0).sin()
+            })
+            .collect();
+        let output = plugin.process_in_blocks(&input, 512);
+        let rms = |x: &[f32]| (x.iter().map(|s| s * s).sum::<f32>() / x.len() as f32).sqrt();
+        let quiet = rms(&output[33075..44100]);
+        let loud = rms(&output[77175..]);
+        // At unity post gain the sub matches the input level
+        assert!((quiet / rms(&input[33075..44100]) - 1.0).abs() < 0.1);
+        assert!((loud / quiet - 10.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_process_detector_channels_select_one_channel() {
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        *plugin.detector_channels_mut() = DetectorChannels {
+            mask: 0b1000,
+            weights: vec![1.0, 1.0, 1.0, 0.5],
+        };
+
+        // Loud material everywhere except on the last channel, which carries a quiet tone
+        let mut channels: Vec<Vec<f32>> = (0..4)
+            .map(|ch| {
+                let level = if ch == 3 { 0.1 } else { 1.0 };
+                (0..4410).map(|i| level * (i as f32 * 0.01 * (ch + 1) as f32).sin()).collect()
+            })
+            .collect();
+        let expected = envelope_follower(&channels[3], 10.0, 10.0, 44100.0);
+        let mut slices: Vec<&mut [f32]> = channels.iter_mut().map(|c| c.as_mut_slice()).collect();
+        plugin.process_channels(&mut slices, || None);
+        assert!((plugin.followers[0].value() - expected.last().unwrap()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_process_custom_curve() {
+        let render = |curve: Vec<f32>| {
+            let mut plugin = plugin_with(|params| {
+                params.dc_block.set(false);
+                params.dry_gain.set(0.0);
+            });
+            plugin.set_custom_curve(Some(curve));
+            // A loud input that would otherwise fully open the envelope
+            plugin.process_in_blocks(&[1.0; 2048], 256)
+        };
+        let ramp: Vec<f32> = (0..1024).map(|i| i as f32 / 1024.0).collect();
+        let full = render(vec![1.0]);
+        let ramped = render(ramp.clone());
+        for i in 0..2048 {
+            let gain = ramp.get(i).copied().unwrap_or(*ramp.last().unwrap());
+            assert!((ramped[i] - full[i] * gain).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_process_max_gen_hz_clamps_frequency() {
+        let mut plugin = plugin_with(|params| {
+            params.pitch.set(900.0);
+            params.max_gen_hz.set(200.0);
+            params.dry_gain.set(0.0);
+        });
+
+        let output = plugin.process_in_blocks(&[1.0; 8820], 512);
+        let sample_rate = 44100.0;
+        let clamped = test_util::magnitude_at(&output[4410..], sample_rate, 200.0);
+        let requested = test_util::magnitude_at(&output[4410..], sample_rate, 900.0);
+        assert!(clamped > 0.3);
+        assert!(requested < 0.01);
+    }
+
+    #[test]
+    fn test_process_env_curve() {
+        let render = |env_curve: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.env_curve.set(env_curve);
+                params.dry_gain.set(0.0);
+            });
+            plugin.process_in_blocks(&[0.25; 4096], 512)
+        };
+        let rms = |x: &[f32]| (x.iter().map(|s| s * s).sum::<f32>() / x.len() as f32).sqrt();
+
+        let linear = render(1.0);
+        let expanded = render(2.0);
+        let default = {
+            let mut plugin = plugin_with(|params| params.dry_gain.set(0.0));
+            plugin.process_in_blocks(&[0.25; 4096], 512)
+        };
+        assert_eq!(linear, default);
+        // 0.25 squared is a quarter of the linear level
+        let ratio = rms(&expanded[2048..]) / rms(&linear[2048..]);
+        assert!((ratio - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_process_decorrelate_widens_above_mono_cutoff() {
+        let render = |decorrelate: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.decorrelate.set(decorrelate);
+                params.mono_below_hz.set(100.0);
+                params.dry_gain.set(0.0);
+            });
+            let mut left = vec![1.0_f32; 8820];
+            let mut right = vec![1.0_f32; 8820];
+            process_stereo(&mut plugin, &mut left, &mut right);
+            (left, right)
+        };
+
+        let mut last_correlation = f32::INFINITY;
+        let (dry_left, dry_right) = render(0.0);
+        for decorrelate in [0.0, 0.5, 1.0] {
+            let (left, right) = render(decorrelate);
+            let correlation = test_util::correlation(&left[4410..], &right[4410..]);
+            assert!(correlation < last_correlation);
+            last_correlation = correlation;
+            for i in 0..left.len() {
+                let sum = left[i] + right[i];
+                assert!((sum - (dry_left[i] + dry_right[i])).abs() < 1e-3);
+            }
+        }
+        assert!(last_correlation < 0.9);
+    }
+
+    #[test]
+    fn test_reset_params_to_default() {
+        // Values can only be changed by the host, so this checks what the plugin asks it to set
+        let plugin = SubrouRs::default();
+        let context = RecordingGuiContext::default();
+        plugin.reset_params_to_default(&context);
+
+        let param_map = plugin.params.param_map();
+        let values = context.values.lock().unwrap();
+        assert!(!param_map.is_empty());
+        assert_eq!(values.len(), param_map.len());
+        let value_of = |id: &str| {
+            let index = param_map.iter().position(|(param_id, _, _)| param_id == id).unwrap();
+            values[index]
+        };
+        let params = &plugin.params;
+        assert_eq!(value_of("pitch"), params.pitch.preview_normalized(440.0));
+        assert_eq!(value_of("link_channels"), params.link_channels.preview_normalized(true));
+        assert_eq!(value_of("freeze"), params.freeze.preview_normalized(false));
+        assert_eq!(value_of("max_voices"), params.max_voices.preview_normalized(4));
+        assert_eq!(value_of("open_db"), params.open_db.preview_normalized(-60.0));
+        assert_eq!(context.open_gestures.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_process_phase_spread_keeps_spectrum() {
+        // With `h4` up the additive saw is the four harmonics the offsets are picked for
+        let spread_plugin = |phase_spread: f32, seed: u32| {
+            let mut plugin = plugin_with(|params| {
+                params.phase_spread.set(phase_spread);
+                params.dry_gain.set(0.0);
+                params.h4.set(1.0);
+            });
+            plugin.set_seed(seed);
+            plugin
+        };
+        let render = |phase_spread: f32, seed: u32| {
+            spread_plugin(phase_spread, seed).process_in_blocks(&[1.0; 8820], 512)
+        };
+        let plain = render(0.0, 0);
+        let spread = render(1.0, 0);
+        assert!(plain[4410..].iter().zip(&spread[4410..]).any(|(a, b)| (a - b).abs() > 0.1));
+        let crest_factor = |out: &[f32]| {
+            let peak = out.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            let rms = (out.iter().map(|s| s * s).sum::<f32>() / out.len() as f32).sqrt();
+            peak / rms
+        };
+        // The sub is peakier with the harmonics in their plain phases, whatever the seed
+        for seed in 0..4 {
+            let spread = render(1.0, seed);
+            assert!(crest_factor(&spread[4410..]) < 0.9 * crest_factor(&plain[4410..]));
+        }
+        for harmonic in 1..=4 {
+            let freq = 440.0 * harmonic as f32;
+            let expected = test_util::magnitude_at(&plain[4410..], 44100.0, freq);
+            let actual = test_util::magnitude_at(&spread[4410..], 44100.0, freq);
+            assert!((expected - actual).abs() < 0.01);
+        }
+
+        // Every harmonic gets its own offset, so other seeds give other shapes rather than the
+        // same wave shifted in time, which would keep the same set of sample values
+        let sorted_cycle = |seed: u32| {
+            let mut cycle = spread_plugin(1.0, seed).capture_cycle(256);
+            cycle.sort_by(f32::total_cmp);
+            cycle
+        };
+        let first = sorted_cycle(0);
+        for seed in 1..4 {
+            let other = sorted_cycle(seed);
+            assert!(first.iter().zip(&other).any(|(a, b)| (a - b).abs() > 0.05));
+        }
+    }
+
+    #[test]
+    fn test_process_channel_trims() {
+        let mut plugin = plugin_with(|params| params.dry_gain.set(0.0));
+        initialize(&mut plugin, 4, 44100.0);
+        plugin.channel_trims_mut()[1] = -6.0;
+
+        let mut channels = vec![vec![1.0_f32; 4096]; 4];
+        let mut slices: Vec<&mut [f32]> = channels.iter_mut().map(|c| c.as_mut_slice()).collect();
+        plugin.process_channels(&mut slices, || None);
+
+        let peak = |channel: &[f32]| channel[2048..].iter().fold(0.0_f32, |a, s| a.max(s.abs()));
+        let reference = peak(&channels[0]);
+        assert!(reference > 0.1);
+        assert!((peak(&channels[1]) / reference - 0.5).abs() < 0.01);
+        assert_eq!(channels[2], channels[0]);
+        assert_eq!(channels[3], channels[0]);
+    }
+
+    #[test]
+    fn test_process_zero_cross_sync_resets_phase() {
+        // The saw runs slower than the input, so every wrap comes from a sync
+        let mut plugin = plugin_with(|params| {
+            params.zero_cross_sync.set(true);
+            params.pitch.set(50.0);
+        });
+
+        let sample_rate = 44100.0;
+        let period = sample_rate / 55.0;
+        let mut resets = Vec::new();
+        let mut last_phase = plugin.saws.phase();
+        for i in 0..8820 {
+            let mut sample = [0.5 * (2.0 * std::f32::consts::PI * i as f32 / period).sin()];
+            plugin.process_channels(&mut [&mut sample], || None);
+            if plugin.saws.phase() < last_phase {
+                resets.push(i);
+            }
+            last_phase = plugin.saws.phase();
+        }
+
+        // One reset per input cycle, trailing each crossing by the low pass' phase lag. That lag
+        // pushes the last of the eleven crossings past the end.
+        assert_eq!(resets.len(), 10);
+        for (cycle, &reset) in resets.iter().enumerate() {
+            let lag = reset as f32 - (cycle + 1) as f32 * period;
+            assert!((0.0..0.1 * period).contains(&lag));
+        }
+    }
+
+    #[test]
+    fn test_process_tiny_buffers() {
+        let mut plugin = plugin_with(|params| {
+            params.track_pitch.set(true);
+            params.phase_align.set(true);
+            params.zero_cross_sync.set(true);
+            params.auto_level.set(true);
+            params.limiter.set(true);
+            params.dither.set(true);
+            params.mono_below_hz.set(100.0);
+            params.decorrelate.set(1.0);
+        });
+
+        let mut left: Vec<f32> = Vec::new();
+        let mut right: Vec<f32> = Vec::new();
+        process_stereo(&mut plugin, &mut left, &mut right);
+        assert!(plugin.process_mono(&[]).is_empty());
+
+        for i in 0..256 {
+            let input = (2.0 * std::f32::consts::PI * 55.0 * i as f32 / 44100.0).sin();
+            let mut left = [input];
+            let mut right = [input];
+            process_stereo(&mut plugin, &mut left, &mut right);
+            assert!(left[0].is_finite() && right[0].is_finite());
+            assert!(plugin.process_mono(&[input])[0].is_finite());
+        }
+    }
+
+    #[test]
+    fn test_process_kick_mode_fires_per_transient() {
+        let mut plugin = plugin_with(|params| {
+            params.kick_mode.set(true);
+            params.pitch.set(60.0);
+            params.kick_amp_decay_ms.set(200.0);
+            params.dry_gain.set(0.0);
+        });
+
+        // Two 5 ms clicks half a second apart
+        let sample_rate = 44100.0;
+        let input: Vec<f32> = (0..44100)
+            .map(|i| {
+                let click = (4410..4631).contains(&i) || (26460..26681).contains(&i);
+                let tone = (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate).sin();
+                if click {
+                    tone
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let output = plugin.process_in_blocks(&input, 512);
+
+        let peak = |range: std::ops::Range<usize>| {
+            output[range].iter().fold(0.0_f32, |acc, s| acc.max(s.abs()))
+        };
+        // Each burst starts loud and has died out well before the next one
+        assert!(peak(4410..6615) > 0.5 && peak(26460..28665) > 0.5);
+        assert!(peak(15435..26460) < 1e-3 && peak(37485..44100) < 1e-3);
+        // Once the pitch sweep has settled the burst sits at the configured pitch
+        let settled = &output[8820..11025];
+        let at_pitch = test_util::magnitude_at(settled, sample_rate, 60.0);
+        assert!(at_pitch > 2.0 * test_util::magnitude_at(settled, sample_rate, 120.0));
+        assert!(at_pitch > 2.0 * test_util::magnitude_at(settled, sample_rate, 30.0));
+    }
+
+    #[test]
+    fn test_process_midi_pitch_bend() {
+        let mut plugin = plugin_with(|params| params.midi_mode.set(true));
+
+        // A2 bent all the way up by the default two semitones
+        let events = vec![
+            NoteEvent::NoteOn { timing: 0, voice_id: None, channel: 0, note: 45, velocity: 1.0 },
+            NoteEvent::MidiPitchBend { timing: 0, channel: 0, value: 1.0 },
+        ];
+        let mut left = vec![0.0_f32; 8820];
+        let mut right = vec![0.0_f32; 8820];
+        process_stereo_with_events(&mut plugin, &mut left, &mut right, events);
+
+        let sample_rate = 44100.0;
+        let note = util::midi_note_to_freq(45);
+        let bent = note * 2.0_f32.powf(2.0 / 12.0);
+        let at_bent = test_util::magnitude_at(&left[4410..], sample_rate, bent);
+        assert!(at_bent > 0.3);
+        assert!(at_bent > 4.0 * test_util::magnitude_at(&left[4410..], sample_rate, note));
+    }
+
+    #[test]
+    fn test_process_fades_in_after_reset() {
+        let render = |fade_in_ms: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.dc_block.set(false);
+                params.fade_in_ms.set(fade_in_ms);
+                params.dry_gain.set(0.0);
+            });
+            plugin.reset();
+            plugin.process_in_blocks(&[1.0; 1024], 64)
+        };
+        let faded = render(10.0);
+        let full = render(0.0);
+
+        // 10 ms is 441 samples, the sub ramps up linearly over them and then matches
+        for (i, (faded, full)) in faded.iter().zip(&full).enumerate() {
+            let gain = (i as f32 / 441.0).min(1.0);
+            assert!((faded - full * gain).abs() < 1e-5);
+        }
+        assert!(faded[..10].iter().all(|s| s.abs() < 0.05));
+    }
+
+    #[test]
+    fn test_process_output_correlation() {
+        let measure = |right: &dyn Fn(f32, f32) -> f32| {
+            // Mute the sub so only the dry signal reaches the meter
+            let mut plugin = plugin_with(|params| params.post_gain.set(0.0));
+            let mut rng = XorShift32::new(7);
+            let mut left: Vec<f32> = (0..22050)
+                .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin())
+                .collect();
+            let mut right: Vec<f32> = left.iter().map(|&l| right(l, rng.next_tpdf())).collect();
+            process_stereo(&mut plugin, &mut left, &mut right);
+            plugin.output_correlation()
+        };
+        assert!((measure(&|left, _| left) - 1.0).abs() < 0.01);
+        assert!((measure(&|left, _| -left) + 1.0).abs() < 0.01);
+        assert!(measure(&|_, noise| 0.5 * noise).abs() < 0.1);
+
+        let mut mono = SubrouRs::default();
+        mono.process_mono(&[0.5; 64]);
+        assert_eq!(mono.output_correlation(), 1.0);
+    }
+
+    #[test]
+    fn test_process_silent_above_nyquist() {
+        for quality in [OscQuality::Additive, OscQuality::BandLimited, OscQuality::Naive] {
+            let render = |pitch: f32| {
+                let mut plugin = plugin_with(|params| {
+                    params.pitch.set(pitch);
+                    params.osc_quality.set(quality);
+                    params.dry_gain.set(0.0);
+                });
+                plugin.update_sample_rate(2000.0);
+                plugin.reset_smoothers();
+                plugin.process_in_blocks(&[0.5; 2000], 100)
+            };
+
+            // Not even the fundamental fits below 1 kHz
+            assert!(render(1500.0).iter().all(|&s| s == 0.0));
+
+            // Only the fundamental fits, what's left is a clean sine at 600 Hz
+            let sine = render(600.0);
+            let total = test_util::band_energy(&sine[1000..], 2000.0, 0.0, 1000.0);
+            let fundamental = test_util::band_energy(&sine[1000..], 2000.0, 590.0, 610.0);
+            assert!(fundamental > 0.0);
+            assert!(fundamental > 0.99 * total);
+        }
+    }
+
+    #[test]
+    fn test_process_fine_tune() {
+        let render = |fine_tune: Option<f32>| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(220.0);
+                if let Some(cents) = fine_tune {
+                    params.fine_tune.set(cents);
+                }
+                params.dry_gain.set(0.0);
+            });
+            plugin.process_in_blocks(&[1.0; 22050], 512)
+        };
+
+        // Zero cents leaves the output untouched
+        assert_eq!(render(Some(0.0)), render(None));
+
+        let semitone = 220.0 * 2.0_f32.powf(1.0 / 12.0);
+        let tuned = render(Some(100.0));
+        let at_semitone = test_util::magnitude_at(&tuned[4410..], 44100.0, semitone);
+        assert!(at_semitone > 0.3);
+        assert!(at_semitone > 4.0 * test_util::magnitude_at(&tuned[4410..], 44100.0, 220.0));
+    }
+
+    #[test]
+    fn test_process_limiter_zero_latency() {
+        let render = |zero_latency: bool| {
+            let mut plugin = plugin_with(|params| {
+                params.limiter.set(true);
+                params.limiter_zero_latency.set(zero_latency);
+            });
+            let mut left: Vec<f32> = (0..8820)
+                .map(|i| 2.0 * (2.0 * std::f32::consts::PI * 50.0 * i as f32 / 44100.0).sin())
+                .collect();
+            let mut right = left.clone();
+            process_stereo(&mut plugin, &mut left, &mut right);
+            (plugin.latency, left)
+        };
+
+        // The lookahead limiter reports its 5 ms as latency, without lookahead there's none
+        assert_eq!(render(false).0, 221);
+        let (latency, output) = render(true);
+        assert_eq!(latency, 0);
+        let ceiling = util::db_to_gain(-0.3);
+        assert!(output.iter().all(|s| s.abs() <= ceiling + 1e-6));
+        assert!(output.iter().any(|s| s.abs() > 0.9 * ceiling));
+    }
+
+    #[test]
+    fn test_process_env_floor_hums_in_silence() {
+        let render = |env_floor: f32| {
+            let mut plugin = plugin_with(|params| params.env_floor.set(env_floor));
+            plugin.process_in_blocks(&[0.0; 4410], 512)
+        };
+        assert!(render(0.0).iter().all(|&s| s == 0.0));
+
+        let hum = render(0.05);
+        let peak = hum.iter().fold(0.0_f32, |acc, s| acc.max(s.abs()));
+        assert!(peak > 0.01 && peak < 0.1);
+    }
+
+    #[test]
+    fn test_process_bypass_crossfades() {
+        let input: Vec<f32> = (0..4096)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut processed = SubrouRs::default();
+        processed.reset_smoothers();
+        let reference = processed.process_in_blocks(&input, 2048);
+
+        // The host switches the bypass on between two blocks
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        let mut output = plugin.process_in_blocks(&input[..2048], 2048);
+        plugin.params = Arc::new(SubrouRsParams {
+            bypass: BoolParam::new("Bypass", true),
+            ..SubrouRsParams::default()
+        });
+        plugin.reset_smoothers();
+        output.extend(plugin.process_in_blocks(&input[2048..], 2048));
+
+        assert_eq!(output[..2048], reference[..2048]);
+        // 10 ms is 441 samples, the processed part fades out linearly over them
+        for i in 2048..2048 + 441 {
+            let wet = 1.0 - (i - 2047) as f32 / 441.0;
+            let expected = input[i] + wet * (reference[i] - input[i]);
+            assert!((output[i] - expected).abs() < 1e-5);
+        }
+        assert!((reference[2048] - input[2048]).abs() > 0.01);
+        assert_eq!(output[2048 + 441..], input[2048 + 441..]);
+    }
+
+    #[test]
+    fn test_capture_cycle_round_trips_through_wavetable() {
+        // With `h4` up the additive saw is four harmonics at any length, the full series would
+        // gain harmonics with the finer capture
+        let plugin = plugin_with(|params| {
+            params.phase_spread.set(0.5);
+            params.octave_level.set(0.5);
+            params.h4.set(0.5);
+        });
+        let cycle = plugin.capture_cycle(256);
+        assert_eq!(cycle.len(), 256);
+        assert!(cycle.iter().any(|s| s.abs() > 0.5));
+
+        // Read back at a finer resolution, the table should match a finer capture
+        let table = Wavetable::new(cycle);
+        let fine = plugin.capture_cycle(1024);
+        for (i, &expected) in fine.iter().enumerate() {
+            let phase = 2.0 * std::f32::consts::PI * i as f32 / 1024.0;
+            assert!((table.sample(phase) - expected).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_process_kick_retrigger_ms() {
+        let render = |retrigger_ms: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.kick_mode.set(true);
+                params.kick_amp_decay_ms.set(50.0);
+                params.retrigger_ms.set(retrigger_ms);
+                params.dry_gain.set(0.0);
+            });
+
+            // Two 5 ms clicks 100 ms apart
+            let input: Vec<f32> = (0..22050)
+                .map(|i| {
+                    let click = (4410..4631).contains(&i) || (8820..9041).contains(&i);
+                    let tone = (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 44100.0).sin();
+                    if click {
+                        tone
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            let output = plugin.process_in_blocks(&input, 512);
+            let peak = |range: std::ops::Range<usize>| {
+                output[range].iter().fold(0.0_f32, |acc, s| acc.max(s.abs()))
+            };
+            (peak(4410..6615), peak(8820..11025))
+        };
+
+        let (first, second) = render(0.0);
+        assert!(first > 0.5 && second > 0.5);
+        // The second click falls within the retrigger window
+        let (first, second) = render(200.0);
+        assert!(first > 0.5);
+        assert!(second < 1e-3);
+    }
+
+    #[test]
+    fn test_process_haas_delays_right_sub() {
+        let render = |mono_below_hz: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.haas_ms.set(10.0);
+                params.mono_below_hz.set(mono_below_hz);
+                params.pitch.set(55.0);
+                params.dry_gain.set(0.0);
+            });
+            let mut left = vec![0.5; 8820];
+            let mut right = left.clone();
+            process_stereo(&mut plugin, &mut left, &mut right);
+            (left, right)
+        };
+
+        // 10 ms is 441 samples
+        let (left, right) = render(0.0);
+        assert!(right[..441].iter().all(|&s| s == 0.0));
+        assert_eq!(right[441..], left[..8820 - 441]);
+
+        // With the mono maker the low band is the same on both sides, only the top is delayed
+        let low_difference = |(left, right): (Vec<f32>, Vec<f32>)| {
+            let difference: Vec<f32> = left.iter().zip(&right).map(|(l, r)| l - r).collect();
+            test_util::band_energy(&difference[4410..], 44100.0, 20.0, 100.0)
+        };
+        assert!(low_difference(render(200.0)) < 0.1 * low_difference(render(0.0)));
+    }
+
+    #[test]
+    fn test_process_midi_cc_modulates_gain() {
+        let render = |events: Vec<PluginNoteEvent<SubrouRs>>| {
+            let mut plugin = plugin_with(|params| params.dry_gain.set(0.0));
+            plugin.mod_matrix_mut().add_route(ModRoute::new(
+                ModSource::MidiCc(7),
+                ModDestination::Gain,
+                1.0,
+            ));
+            let mut left = vec![0.5; 4410];
+            let mut right = left.clone();
+            process_stereo_with_events(&mut plugin, &mut left, &mut right, events);
+            left
+        };
+        let plain = render(Vec::new());
+        // Half way up with a depth of one adds 0.5 to the unity post gain
+        let modulated = render(vec![NoteEvent::MidiCC {
+            timing: 0,
+            channel: 0,
+            cc: 7,
+            value: 0.5,
+        }]);
+        assert!(plain.iter().any(|s| s.abs() > 0.1));
+        for (plain, modulated) in plain.iter().zip(&modulated) {
+            assert!((modulated - 1.5 * plain).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_process_hi_shelf() {
+        let render = |gain_db: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(100.0);
+                params.osc_quality.set(OscQuality::BandLimited);
+                params.hi_shelf_gain_db.set(gain_db);
+                params.dry_gain.set(0.0);
+            });
+            plugin.process_in_blocks(&[1.0; 22050], 512)
+        };
+        let flat = render(0.0);
+        let cut = render(-12.0);
+
+        // The fundamental sits well below the 1 kHz shelf
+        let fundamental =
+            |output: &[f32]| test_util::magnitude_at(&output[4410..], 44100.0, 100.0);
+        assert!((fundamental(&cut) / fundamental(&flat) - 1.0).abs() < 0.05);
+        let highs =
+            |output: &[f32]| test_util::band_energy(&output[4410..], 44100.0, 3000.0, 8000.0);
+        assert!(highs(&cut) < 0.1 * highs(&flat));
+    }
+
+    #[test]
+    fn test_process_clip_indicator() {
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        assert!(!plugin.clipped());
+        plugin.process_mono(&[1.5; 64]);
+        assert!(plugin.clipped());
+        // The flag stays latched through quiet blocks until it's cleared
+        plugin.process_mono(&[0.0; 64]);
+        assert!(plugin.clipped());
+
+        plugin.reset_clip();
+        plugin.process_mono(&[0.1; 4410]);
+        assert!(!plugin.clipped());
+    }
+
+    #[test]
+    fn test_process_lfo_sweeps_filter_cutoff() {
+        let mut plugin = plugin_with(|params| {
+            params.cutoff_hz.set(1000.0);
+            params.cutoff_min_hz.set(500.0);
+            params.cutoff_max_hz.set(3000.0);
+        });
+        plugin.mod_matrix_mut().lfo_hz = 2.0;
+        plugin.mod_matrix_mut().add_route(ModRoute::new(
+            ModSource::Lfo,
+            ModDestination::Filter,
+            2.0,
+        ));
+
+        // Two octaves either way would reach 250 Hz and 4 kHz without the bounds
+        let cutoffs: Vec<f32> = (0..(55125 / 64))
+            .map(|_| {
+                plugin.process_mono(&[0.5; 64]);
+                plugin.filter_cutoff()
+            })
+            .collect();
+        assert!(cutoffs.iter().all(|&hz| (500.0..=3000.0).contains(&hz)));
+        assert!(cutoffs.contains(&500.0) && cutoffs.contains(&3000.0));
+        // 1.25 s at 2 Hz rises through the base cutoff at 0.5 s and 1 s
+        let rising = cutoffs.windows(2).filter(|w| w[0] < 1000.0 && w[1] >= 1000.0).count();
+        assert_eq!(rising, 2);
+    }
+
+    #[test]
+    fn test_process_true_peak() {
+        // Mute the sub so only the dry signal reaches the meter
+        let mut plugin = plugin_with(|params| params.post_gain.set(0.0));
+        // A quarter of the sample rate at 45 degrees peaks between the samples
+        let input: Vec<f32> = (0..512)
+            .map(|i| {
+                let phase = std::f32::consts::FRAC_PI_4 + std::f32::consts::FRAC_PI_2 * i as f32;
+                0.9 * phase.sin()
+            })
+            .collect();
+        let output = plugin.process_mono(&input);
+        let sample_peak = output.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert!(sample_peak < 0.65);
+        assert!(plugin.true_peak() > 1.3 * sample_peak);
+        assert!(!plugin.clipped());
+    }
+
+    #[test]
+    fn test_process_detector_gain() {
+        let render = |gain_db: f32| {
+            // Mute the sub so the output is only the dry signal
+            let mut plugin = plugin_with(|params| {
+                params.post_gain.set(0.0);
+                params.detector_gain_db.set(gain_db);
+            });
+            let output = plugin.process_mono(&[0.01; 4410]);
+            (output, plugin.followers[0].value())
+        };
+        let (plain, plain_envelope) = render(0.0);
+        let (boosted, boosted_envelope) = render(20.0);
+        assert_eq!(plain, boosted);
+        assert!((plain_envelope - 0.01).abs() < 1e-4);
+        assert!((boosted_envelope - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_process_formant() {
+        let render = |formant_hz: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(100.0);
+                params.osc_quality.set(OscQuality::BandLimited);
+                params.formant_hz.set(formant_hz);
+                params.dry_gain.set(0.0);
+            });
+            plugin.process_in_blocks(&[1.0; 22050], 512)
+        };
+        let plain = render(0.0);
+        let formant = render(800.0);
+        let magnitude = |output: &[f32], freq: f32| {
+            test_util::magnitude_at(&output[4410..], 44100.0, freq)
+        };
+        // The harmonic at the center doubles, the fundamental far below it barely moves
+        assert!(magnitude(&formant, 800.0) > 1.8 * magnitude(&plain, 800.0));
+        assert!((magnitude(&formant, 100.0) / magnitude(&plain, 100.0) - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_process_high_precision_phase() {
+        let num_samples = 220500;
+        let phase_error = |precision: Precision| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(100.0);
+                params.precision.set(precision);
+            });
+            plugin.process_in_blocks(&vec![0.5; num_samples], 512);
+            let expected = (2.0 * std::f64::consts::PI * 100.0 * num_samples as f64 / 44100.0)
+                .rem_euclid(2.0 * std::f64::consts::PI);
+            let error = (plugin.saws.phase() as f64 - expected).abs();
+            error.min(2.0 * std::f64::consts::PI - error)
+        };
+        // Five seconds is enough for the single precision phase to drift noticeably
+        let standard = phase_error(Precision::Standard);
+        let high = phase_error(Precision::HighPrecision);
+        assert!(high < 1e-5);
+        assert!(standard > 10.0 * high);
+    }
+
+    #[test]
+    fn test_process_amount_macro() {
+        let render = |amount: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.pitch.set(100.0);
+                params.amount.set(amount);
+                params.dry_gain.set(0.0);
+            });
+            let output = plugin.process_in_blocks(&[0.5; 22050], 512);
+            let rms = (output[4410..].iter().map(|s| s * s).sum::<f32>()
+                / (output.len() - 4410) as f32)
+                .sqrt();
+            (rms, test_util::band_energy(&output[4410..], 44100.0, 150.0, 5000.0))
+        };
+        let sweep: Vec<(f32, f32)> = [0.0, 0.25, 0.5, 0.75, 1.0].map(render).to_vec();
+        for pair in sweep.windows(2) {
+            assert!(pair[1].0 > pair[0].0);
+            assert!(pair[1].1 > pair[0].1);
+        }
+    }
+
+    #[test]
+    fn test_process_key_filter() {
+        let envelope = |tones: &[(f32, f32)], keyed: bool| {
+            let mut plugin = plugin_with(|params| {
+                params.key_low_hz.set(if keyed { 40.0 } else { MIN_CUTOFF_HZ });
+                params.key_high_hz.set(if keyed { 120.0 } else { MAX_CUTOFF_HZ });
+            });
+            let input: Vec<f32> = (0..8820)
+                .map(|i| {
+                    let t = 2.0 * std::f32::consts::PI * i as f32 / 44100.0;
+                    tones.iter().map(|&(freq, level)| level * (freq * t).sin()).sum()
+                })
+                .collect();
+            plugin.process_mono(&input);
+            plugin.followers[0].value()
+        };
+        // A 60 Hz kick region tone against a 3 kHz one
+        let kick = [(60.0, 0.5)];
+        let hats = [(3000.0, 0.5)];
+        let open = envelope(&hats, false);
+        assert!(open > 0.25);
+        assert!(envelope(&hats, true) < 0.01 * open);
+        assert!(envelope(&kick, true) > 0.15);
+        let both = envelope(&[(60.0, 0.5), (3000.0, 0.5)], true);
+        assert!((both - envelope(&kick, true)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_process_compressor_stereo_link() {
+        let gain_reduction = |stereo_link: bool| {
+            // Mute the sub so only the dry signal reaches the compressor
+            let mut plugin = plugin_with(|params| {
+                params.post_gain.set(0.0);
+                params.compressor_threshold_db.set(-20.0);
+                params.compressor_ratio.set(4.0);
+                params.stereo_link.set(stereo_link);
+            });
+            let mut left = vec![0.8; 4410];
+            let mut right = vec![0.2; 4410];
+            process_stereo(&mut plugin, &mut left, &mut right);
+            (
+                plugin.compressor.gain_reduction_db(0),
+                plugin.compressor.gain_reduction_db(1),
+                left[4409] / right[4409],
+            )
+        };
+        // Linked, the louder left channel sets the gain for both and the balance is kept
+        let (left, right, balance) = gain_reduction(true);
+        assert!(left > 1.0);
+        assert_eq!(left, right);
+        assert!((balance - 4.0).abs() < 1e-4);
+
+        let (left, right, balance) = gain_reduction(false);
+        assert!(left > right + 1.0);
+        assert!(balance < 3.5);
+    }
+
+    #[test]
+    fn test_latency_samples_matches_lookahead() {
+        let plugin = |limiter: bool, lookahead_ms: f32| {
+            plugin_with(|params| {
+                params.limiter.set(limiter);
+                params.limiter_lookahead_ms.set(lookahead_ms);
+            })
+        };
+        // 2 ms at 44.1 kHz is 88.2 samples
+        let mut lookahead = plugin(true, 2.0);
+        assert_eq!(lookahead.latency_samples(), 88);
+        lookahead.update_sample_rate(96000.0);
+        assert_eq!(lookahead.latency_samples(), 192);
+        assert_eq!(plugin(false, 2.0).latency_samples(), 0);
+    }
+
+    #[test]
+    fn test_process_curve_smoothing() {
+        let gain_curve = |curve_smooth_ms: f32| {
+            let mut plugin = plugin_with(|params| params.curve_smooth_ms.set(curve_smooth_ms));
+            // A click every 10 ms
+            let mut input: Vec<f32> =
+                (0..22050).map(|i| if i % 441 < 20 { 1.0 } else { 0.0 }).collect();
+            plugin.process_channels(&mut [input.as_mut_slice()], || None)
+        };
+        let ripple = |curve: &[f32]| test_util::band_energy(&curve[4410..], 44100.0, 50.0, 5000.0);
+        let raw = gain_curve(0.0);
+        let smooth = gain_curve(5.0);
+        let smoother = gain_curve(20.0);
+        assert!(ripple(&smooth) < 0.5 * ripple(&raw));
+        assert!(ripple(&smoother) < 0.5 * ripple(&smooth));
+    }
+
+    #[test]
+    fn test_process_seed_is_reproducible() {
+        let render = |seed: i32, set_seed: Option<u32>| {
+            let mut plugin = plugin_with(|params| {
+                params.dither.set(true);
+                params.phase_spread.set(1.0);
+                params.seed.set(seed);
+            });
+            plugin.reset();
+            if let Some(seed) = set_seed {
+                plugin.set_seed(seed);
+            }
+            plugin.process_in_blocks(&[0.5; 4410], 512)
+        };
+        assert_eq!(render(1, None), render(1, None));
+        assert_ne!(render(1, None), render(2, None));
+        // `set_seed` wins over the parameter
+        assert_eq!(render(1, Some(2)), render(2, None));
+        // The default seed keeps the generators as they were
+        assert_eq!(SubrouRs::default().rng, seeded_rng(0));
+    }
+
+    #[test]
+    fn test_initialize_sizes_channel_state() {
+        // Nothing per channel should have to grow on the audio thread
+        let mut plugin = SubrouRs::default();
+        initialize(&mut plugin, 6, 48000.0);
+        assert_eq!(plugin.true_peak_meters.len(), 6);
+        assert_eq!(plugin.key_filters.len(), 6);
+        assert_eq!(plugin.dc_blockers.len(), 6);
+        assert!(plugin
+            .dc_blockers
+            .iter()
+            .all(|blocker| *blocker == DcBlocker::new(filter::DC_BLOCK_HZ, 48000.0)));
+    }
+
+    #[test]
+    fn test_process_block_larger_than_max_buffer_size() {
+        let input: Vec<f32> = (0..2048)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let render = |max_buffer_size: u32| {
+            let mut plugin = SubrouRs::default();
+            let layout = AudioIOLayout {
+                main_output_channels: NonZeroU32::new(1),
+                ..AudioIOLayout::const_default()
+            };
+            let config = BufferConfig {
+                sample_rate: 44100.0,
+                min_buffer_size: None,
+                max_buffer_size,
+                process_mode: ProcessMode::Realtime,
+            };
+            assert!(plugin.initialize(&layout, &config, &mut DummyInitContext));
+            plugin.reset_smoothers();
+            // Switch the bypass on after the first block so the crossfade needs the dry copy
+            let mut output = plugin.process_in_blocks(&input[..1024], 1024);
+            plugin.params = Arc::new(SubrouRsParams {
+                bypass: BoolParam::new("Bypass", true),
+                ..SubrouRsParams::default()
+            });
+            plugin.reset_smoothers();
+            output.extend(plugin.process_in_blocks(&input[1024..], 1024));
+            (output, plugin.dry_scratch[0].len())
+        };
+
+        // The host announced 64 samples but sends blocks of 1024
+        let (output, scratch_len) = render(64);
+        let (reference, _) = render(1024);
+        assert_eq!(scratch_len, 1024);
+        assert_eq!(output, reference);
+        assert!(output.iter().all(|s| s.is_finite()));
+        // Fully bypassed once the fade is over
+        assert_eq!(output[2047], input[2047]);
+    }
+
+    #[test]
+    fn test_process_max_note_gates_midi_notes() {
+        let render = |note: u8| {
+            let mut plugin = plugin_with(|params| {
+                params.midi_mode.set(true);
+                params.dry_gain.set(0.0);
+                // C2
+                params.max_note.set(36);
+            });
+            let mut left = vec![0.0; 4410];
+            let mut right = vec![0.0; 4410];
+            let events = vec![NoteEvent::NoteOn {
+                timing: 0,
+                voice_id: None,
+                channel: 0,
+                note,
+                velocity: 1.0,
+            }];
+            process_stereo_with_events(&mut plugin, &mut left, &mut right, events);
+            left
+        };
+
+        // A2 is above the threshold and generates nothing
+        assert!(render(45).iter().all(|&s| s == 0.0));
+        // A1 plays as usual
+        let below = render(33);
+        let a1 = util::midi_note_to_freq(33);
+        assert!(test_util::magnitude_at(&below[2205..], 44100.0, a1) > 0.1);
+    }
+
+    #[test]
+    fn test_process_amp_env_shapes_sub() {
+        let mut plugin = plugin_with(|params| {
+            params.dry_gain.set(0.0);
+            params.amp_env.set(true);
+            params.amp_attack_ms.set(10.0);
+            params.amp_decay_ms.set(50.0);
+            params.amp_sustain.set(0.25);
+        });
+        // A steady input keeps the input envelope flat
+        let mut input: Vec<f32> = (0..8820)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let sub_level = plugin.process_channels(&mut [input.as_mut_slice()], || None);
+        // With the dry signal muted the buffer now only holds the generated sub
+        let output = input;
+
+        let start = sub_level.iter().position(|&level| level > 0.0).unwrap();
+        let peak = sub_level.iter().cloned().fold(0.0, f32::max);
+        // The peak is reached after the 10 ms attack
+        let peak_at = sub_level.iter().position(|&level| level == peak).unwrap();
+        assert!(((peak_at - start) as f32 / 44.1 - 10.0).abs() < 0.1);
+        // Then it decays to the sustain level and stays there
+        let sustain = sub_level[start + 4410];
+        assert!((sustain / peak - 0.25).abs() < 1e-3);
+        assert!((sub_level[8819] - sustain).abs() < 1e-6);
+        // The generated sub follows the same shape
+        let rms = |range: std::ops::Range<usize>| {
+            (output[range.clone()].iter().map(|s| s * s).sum::<f32>() / range.len() as f32).sqrt()
+        };
+        // 10 ms (about four cycles) of the 440 Hz sub right after the attack is still close to
+        // full level
+        let attack = rms(start + 441..start + 882);
+        let sustained = rms(8820 - 441..8820);
+        assert!((0.2..0.3).contains(&(sustained / attack)));
+    }
+
+    #[test]
+    fn test_process_amp_env_releases_with_env_floor() {
+        let mut plugin = plugin_with(|params| {
+            params.dry_gain.set(0.0);
+            params.amp_env.set(true);
+            params.amp_release_ms.set(50.0);
+            params.env_floor.set(0.5);
+        });
+        // The floor keeps the input envelope up in silence, the gate closing still releases the
+        // amp envelope
+        let mut input: Vec<f32> = (0..4410)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / 44100.0).sin())
+            .collect();
+        input.resize(22050, 0.0);
+        let sub_level = plugin.process_channels(&mut [input.as_mut_slice()], || None);
+        assert!(sub_level[4000] > 0.5);
+        assert!(sub_level[22049] < 1e-3);
+
+        // Silence alone never triggers it
+        let mut silence = vec![0.0_f32; 4410];
+        let sub_level = plugin.process_channels(&mut [silence.as_mut_slice()], || None);
+        assert!(sub_level.iter().all(|&level| level < 1e-3));
+    }
+
+    #[test]
+    fn test_process_dc_block() {
+        let render = |dc_block: bool| {
+            let mut plugin = plugin_with(|params| {
+                params.dc_block.set(dc_block);
+                params.post_gain.set(0.0);
+            });
+            // A quarter full scale offset under a 100 Hz sine
+            let input: Vec<f32> = (0..44100)
+                .map(|i| {
+                    0.25 + 0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin()
+                })
+                .collect();
+            let output = plugin.process_in_blocks(&input, 512);
+            let tail = &output[22050..];
+            tail.iter().sum::<f32>() / tail.len() as f32
+        };
+
+        assert!((render(false) - 0.25).abs() < 1e-3);
+        assert!(render(true).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_pitch_param_skew_and_smoothing() {
+        let params = SubrouRsParams::default();
+        // A linear range would leave under 5% of the control below 100 Hz
+        let linear_share = (100.0 - 10.0) / (2000.0 - 10.0);
+        let share = params.pitch.range().normalize(100.0);
+        assert!(share > 4.0 * linear_share);
+
+        // A jump from 40 Hz to 400 Hz is spread over 20 ms in equal ratios
+        params.pitch.smoothed.reset(40.0);
+        params.pitch.smoothed.set_target(44100.0, 400.0);
+        let sweep: Vec<f32> = (0..2205).map(|_| params.pitch.smoothed.next()).collect();
+        let max_ratio = sweep
+            .windows(2)
+            .map(|pair| pair[1] / pair[0])
+            .fold(0.0_f32, f32::max);
+        assert!(max_ratio < 1.003);
+        assert_eq!(sweep[2204], 400.0);
+        assert!(sweep[880] < 400.0);
+    }
+
+    #[test]
+    fn test_render_normalized_to_peak() {
+        let mut plugin = SubrouRs::default();
+        plugin.reset_smoothers();
+        let input: Vec<f32> = (0..8820)
+            .map(|i| 0.25 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut left = plugin.process_in_blocks(&input, 512);
+        let mut right: Vec<f32> = left.iter().map(|s| 0.5 * s).collect();
+        let crest = |samples: &[f32]| {
+            let peak = samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+            peak / rms
+        };
+        let crest_before = crest(&left);
+
+        let gain = normalize_peak(&mut [&mut left, &mut right], -1.0);
+        assert!(gain > 1.0);
+        let peak = left.iter().chain(&right).fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert!((util::gain_to_db(peak) + 1.0).abs() < 0.01);
+        // Both channels get the same gain and the crest factor doesn't change
+        assert!(left.iter().zip(&right).all(|(l, r)| (0.5 * l - r).abs() < 1e-6));
+        assert!((crest(&left) - crest_before).abs() < 1e-3);
+
+        let mut silence = vec![0.0; 64];
+        assert_eq!(normalize_peak(&mut [&mut silence], -1.0), 1.0);
+        assert!(silence.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_harmonic_levels() {
+        let plugin = plugin_with(|params| {
+            params.h1.set(0.0);
+            params.h3.set(0.0);
+        });
+        // One period in 64 samples, so harmonic n lands on bin n
+        let cycle = plugin.capture_cycle(64);
+        let second = test_util::magnitude_at(&cycle, 64.0, 2.0);
+        assert!((second - std::f32::consts::FRAC_1_PI).abs() < 1e-4);
+        for harmonic in [1.0, 3.0, 4.0, 5.0] {
+            assert!(test_util::magnitude_at(&cycle, 64.0, harmonic) < 1e-5);
+        }
+
+        // The defaults are the full saw, up to the capture's Nyquist. Its edge is steep enough
+        // that the rounding of the accumulated phase shows there.
+        let cycle = SubrouRs::default().capture_cycle(64);
+        let expected: Vec<f32> = (0..64)
+            .map(|i| saw_wave(2.0 * std::f32::consts::PI * i as f32 / 64.0, 32))
+            .collect();
+        assert!(cycle.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_process_aux_source() {
+        let render = |source: InputSource| {
+            // A pitch off the harmonics of the aux tone, so it only shows up if the sub stays on it
+            let mut plugin = plugin_with(|params| {
+                params.source.set(source);
+                params.track_pitch.set(true);
+                params.pitch.set(470.0);
+            });
+
+            // Silence on the main input and a 55 Hz tone on the aux input
+            let mut left = vec![0.0_f32; 22050];
+            let mut right = vec![0.0_f32; 22050];
+            let mut aux_left: Vec<f32> = (0..22050)
+                .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 55.0 * i as f32 / 44100.0).sin())
+                .collect();
+            let mut aux_right = aux_left.clone();
+            for start in (0..22050).step_by(512) {
+                let end = (start + 512).min(22050);
+                let mut buffer = Buffer::default();
+                let mut aux_buffer = Buffer::default();
+                unsafe {
+                    buffer.set_slices(end - start, |out| {
+                        *out = vec![&mut left[start..end], &mut right[start..end]]
+                    });
+                    aux_buffer.set_slices(end - start, |out| {
+                        *out = vec![&mut aux_left[start..end], &mut aux_right[start..end]]
+                    });
+                }
+                let mut aux_inputs = [aux_buffer];
+                let mut aux = AuxiliaryBuffers { inputs: &mut aux_inputs, outputs: &mut [] };
+                plugin.process(&mut buffer, &mut aux, &mut DummyContext::default());
+            }
+            (left, plugin.detected_pitch())
+        };
+
+        let (output, detected) = render(InputSource::Aux);
+        assert!((detected.unwrap() - 55.0).abs() < 1.0);
+        // The sub follows the aux tone's pitch
+        let at_55 = test_util::magnitude_at(&output[11025..], 44100.0, 55.0);
+        let at_470 = test_util::magnitude_at(&output[11025..], 44100.0, 470.0);
+        assert!(at_55 > 0.1);
+        assert!(at_55 > 10.0 * at_470);
+
+        // The main input is silent, so it generates nothing
+        let (output, _) = render(InputSource::Main);
+        assert!(output.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_process_aux_source_only_feeds_its_block() {
+        let mut plugin = plugin_with(|params| params.source.set(InputSource::Aux));
+        let mut left = vec![0.0_f32; 512];
+        let mut right = vec![0.0_f32; 512];
+        let mut aux_left: Vec<f32> = (0..512)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 55.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut aux_right = aux_left.clone();
+        let mut buffer = Buffer::default();
+        let mut aux_buffer = Buffer::default();
+        unsafe {
+            buffer.set_slices(512, |out| *out = vec![&mut left, &mut right]);
+            aux_buffer.set_slices(512, |out| *out = vec![&mut aux_left, &mut aux_right]);
+        }
+        let mut aux_inputs = [aux_buffer];
+        let mut aux = AuxiliaryBuffers { inputs: &mut aux_inputs, outputs: &mut [] };
+        plugin.process(&mut buffer, &mut aux, &mut DummyContext::default());
+
+        // Later blocks without an aux input fall back to the silent main input
+        let output = plugin.process_in_blocks(&[0.0; 44100], 512);
+        assert!(output[39690..].iter().all(|s| s.abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_process_loudness_matched_bypass() {
+        let input: Vec<f32> = (0..44100)
+            .map(|i| 0.25 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let rms_db = |bypass: bool, bypass_match: bool| {
+            let mut plugin = plugin_with(|params| {
+                params.bypass.set(bypass);
+                params.bypass_match.set(bypass_match);
+                params.post_gain.set(2.0);
+            });
+            let output = plugin.process_in_blocks(&input, 512);
+            let tail = &output[22050..];
+            let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+            util::gain_to_db(rms)
+        };
+
+        let processed = rms_db(false, true);
+        // The sub makes the processed signal louder than the plain input
+        assert!(processed - rms_db(true, false) > 2.0);
+        assert!((processed - rms_db(true, true)).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_process_loudness_matched_bypass_ignores_stale_channels() {
+        let mut plugin = plugin_with(|params| params.bypass_match.set(true));
+        let mut left: Vec<f32> = (0..512).map(|i| (i as f32 * 0.05).sin()).collect();
+        let mut right = left.clone();
+        process_stereo(&mut plugin, &mut left, &mut right);
+
+        // The right channel's scratch still holds the stereo block
+        plugin.process_in_blocks(&[0.0; 44100], 512);
+        assert!(plugin.bypass_match_dry.value() < 0.01);
+    }
+
+    #[test]
+    fn test_process_detector_weights_surround() {
+        let mut plugin = plugin_with(|params| {
+            params.detector_mono_mode.set(DetectorMonoMode::WeightedSum);
+        });
+        // L, R, C, LFE, Ls, Rs with the centre and the surrounds left out and the LFE at half
+        let weights = vec![1.0, 1.0, 0.0, 0.5, 0.0, 0.0];
+        plugin.set_detector_weights(weights.clone());
+
+        let amplitudes = [0.1, 0.2, 0.9, 0.4, 0.9, 0.9];
+        let tone = |i: usize| (2.0 * std::f32::consts::PI * 50.0 * i as f32 / 44100.0).sin();
+        let mut channels: Vec<Vec<f32>> = amplitudes
+            .iter()
+            .map(|amplitude| (0..8820).map(|i| amplitude * tone(i)).collect())
+            .collect();
+        let weighted: Vec<f32> = (0..8820)
+            .map(|i| (0..6).map(|ch| weights[ch] * channels[ch][i]).sum())
+            .collect();
+        let expected = envelope_follower(&weighted, 10.0, 10.0, 44100.0);
+
+        let mut slices: Vec<&mut [f32]> = channels.iter_mut().map(|c| c.as_mut_slice()).collect();
+        plugin.process_channels(&mut slices, || None);
+        let envelope = plugin.followers[0].value();
+        assert!((envelope - expected.last().unwrap()).abs() < 1e-6);
+        // The tones are in phase, so the detector sees them at 0.1 + 0.2 + 0.5 * 0.4
+        let peak = weighted.iter().cloned().fold(0.0, f32::max);
+        assert!((peak - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_process_drift() {
+        // Periods of a 100 Hz sine, from the interpolated rising zero crossings
+        let periods = |drift: f32| {
+            let mut plugin = plugin_with(|params| {
+                params.drift.set(drift);
+                params.pitch.set(100.0);
+                params.h2.set(0.0);
+                params.h3.set(0.0);
+                params.dry_gain.set(0.0);
+                params.dc_block.set(false);
+            });
+            let output = plugin.process_in_blocks(&vec![1.0; 176400], 512);
+            let crossings: Vec<f32> = output[4410..]
+                .windows(2)
+                .enumerate()
+                .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+                .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+                .collect();
+            crossings.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<f32>>()
+        };
+
+        let stable = periods(0.0);
+        // Only limited by the float precision of the crossing times
+        assert!(stable.iter().all(|period| (period - 441.0).abs() < 0.05));
+
+        let drifting = periods(20.0);
+        let cents: Vec<f32> = drifting
+            .iter()
+            .map(|period| 1200.0 * (441.0 / period).log2())
+            .collect();
+        // It stays within the drift range and moves slowly from one cycle to the next
+        assert!(cents.iter().all(|c| c.abs() <= 20.5));
+        assert!(cents.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 0.5));
+        let min = cents.iter().cloned().fold(f32::MAX, f32::min);
+        let max = cents.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(max - min > 2.0);
+    }
+
+    #[test]
+    fn test_process_current_gain_db() {
+        // Duck the sub under the input, the inverted envelope takes the gain from full level
+        // down to nothing as the input gets louder
+        let mut plugin = plugin_with(|params| params.post_gain.set(0.0));
+        plugin
+            .mod_matrix_mut()
+            .add_route(ModRoute::new(ModSource::Envelope, ModDestination::Gain, 1.0).inverted());
+        assert_eq!(plugin.current_gain_db(), util::MINUS_INFINITY_DB);
+
+        // A full scale input ducks the sub all the way
+        plugin.process_mono(&[1.0; 8820]);
+        let loud_db = plugin.current_gain_db();
+        assert!(loud_db < -40.0, "{loud_db}");
+
+        // A quiet one barely touches it
+        plugin.process_mono(&[0.001; 8820]);
+        let quiet_db = plugin.current_gain_db();
+        assert!(quiet_db > -0.1, "{quiet_db}");
+
+        // Without any ducking the gain stage sits at full level whatever the input
+        let mut plugin = plugin_with(|_| {});
+        plugin.process_mono(&[0.001; 8820]);
+        assert!(plugin.current_gain_db().abs() < 0.01);
     }
 }
@@ -0,0 +1,183 @@
+use crate::envelope::{flush_denormal, sample_coefficient};
+
+/// Stereo linked lookahead peak limiter. The audio is delayed by the lookahead while the gain
+/// computer already sees the incoming peaks, so the gain reduction ramps in before a transient
+/// reaches the output instead of reacting after it. Anything the ramp doesn't catch gets clamped
+/// to the ceiling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limiter {
+    /// Per channel delay lines, allocated for the maximum lookahead.
+    delay: Vec<Vec<f32>>,
+    /// Required gain for every sample currently inside the lookahead window.
+    required: Vec<f32>,
+    lookahead: usize,
+    pos: usize,
+    required_pos: usize,
+    gain: f32,
+    knee_db: f32,
+}
+
+impl Limiter {
+    pub fn new(num_channels: usize, max_lookahead: usize) -> Self {
+        Self {
+            delay: vec![vec![0.0; max_lookahead]; num_channels],
+            required: vec![1.0; max_lookahead + 1],
+            lookahead: 0,
+            pos: 0,
+            required_pos: 0,
+            gain: 1.0,
+            knee_db: 0.0,
+        }
+    }
+
+    /// Width of the soft knee below the ceiling, `0` limits with a hard knee.
+    pub fn set_knee_db(&mut self, knee_db: f32) {
+        self.knee_db = knee_db.max(0.0);
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.delay.len()
+    }
+
+    /// The current lookahead in samples, this is also the latency the limiter adds.
+    pub fn lookahead(&self) -> usize {
+        self.lookahead
+    }
+
+    /// Change the lookahead, clamped to the maximum the limiter was created with. Changing it
+    /// clears the delay lines.
+    pub fn set_lookahead(&mut self, samples: usize) {
+        let samples = samples.min(self.required.len() - 1);
+        if samples != self.lookahead {
+            self.lookahead = samples;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for line in &mut self.delay {
+            line.fill(0.0);
+        }
+        self.required.fill(1.0);
+        self.pos = 0;
+        self.required_pos = 0;
+        self.gain = 1.0;
+    }
+
+    /// Limit the channels in place so no sample exceeds `ceiling`. `release_coeff` is the one
+    /// pole coefficient used when the gain recovers.
+    pub fn process(&mut self, channels: &mut [&mut [f32]], ceiling: f32, release_coeff: f32) {
+        let num_samples = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        let window = self.lookahead + 1;
+        // Reach (most of) the required gain by the time the peak leaves the delay line
+        let attack_coeff = sample_coefficient(self.lookahead as f32);
+
+        for i in 0..num_samples {
+            let peak = channels.iter().fold(0.0_f32, |acc, c| acc.max(c[i].abs()));
+            self.required[self.required_pos] = knee_gain(peak, ceiling, self.knee_db);
+            self.required_pos = (self.required_pos + 1) % window;
+
+            let target = self.required[..window]
+                .iter()
+                .fold(1.0_f32, |acc, &g| acc.min(g));
+            let coeff = if target < self.gain {
+                attack_coeff
+            } else {
+                release_coeff
+            };
+            self.gain = flush_denormal(self.gain + coeff * (target - self.gain));
+
+            for (channel, line) in channels.iter_mut().zip(&mut self.delay) {
+                let delayed = if self.lookahead == 0 {
+                    channel[i]
+                } else {
+                    std::mem::replace(&mut line[self.pos], channel[i])
+                };
+                channel[i] = (delayed * self.gain).clamp(-ceiling, ceiling);
+            }
+            if self.lookahead > 0 {
+                self.pos = (self.pos + 1) % self.lookahead;
+            }
+        }
+    }
+}
+
+/// Gain needed to bring `peak` down to `ceiling`. With a knee, the reduction already starts
+/// `knee_db / 2` below the ceiling and eases in quadratically, it still never exceeds the ceiling.
+fn knee_gain(peak: f32, ceiling: f32, knee_db: f32) -> f32 {
+    if knee_db <= 0.0 {
+        return if peak > ceiling { ceiling / peak } else { 1.0 };
+    }
+
+    // Shift the knee down so it ends at the ceiling instead of being centered on it
+    let threshold_db = 20.0 * ceiling.log10() - knee_db / 2.0;
+    let over_db = 20.0 * peak.max(f32::MIN_POSITIVE).log10() - threshold_db;
+    let reduction_db = if over_db <= -knee_db / 2.0 {
+        0.0
+    } else if over_db < knee_db / 2.0 {
+        (over_db + knee_db / 2.0).powi(2) / (2.0 * knee_db)
+    } else {
+        over_db
+    };
+    10.0_f32.powf(-reduction_db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_delays_by_lookahead() {
+        let mut limiter = Limiter::new(1, 64);
+        limiter.set_lookahead(10);
+        let mut samples = vec![0.0_f32; 32];
+        samples[0] = 0.5;
+        limiter.process(&mut [samples.as_mut_slice()], 1.0, 0.01);
+        assert_eq!(samples[10], 0.5);
+        assert!(samples
+            .iter()
+            .enumerate()
+            .all(|(i, &s)| i == 10 || s == 0.0));
+    }
+
+    #[test]
+    fn test_limiter_reduces_gain_before_peak() {
+        let lookahead = 220;
+        let mut limiter = Limiter::new(1, 1024);
+        limiter.set_lookahead(lookahead);
+        let mut samples = vec![0.5_f32; 2000];
+        samples[1000] = 2.0;
+        limiter.process(&mut [samples.as_mut_slice()], 1.0, 0.001);
+
+        // The peak comes out `lookahead` samples later, the gain already moves before that
+        let peak = 1000 + lookahead;
+        assert!(samples[peak - 50] < 0.49);
+        assert!(samples[peak - 1] < samples[peak - 50]);
+        assert!(samples.iter().all(|&s| s.abs() <= 1.0));
+    }
+
+    #[test]
+    fn test_knee_gain_is_smooth() {
+        // Sweep from 12 dB below to 6 dB above the ceiling in 0.1 dB steps
+        let ceiling = 0.5;
+        let levels: Vec<f32> = (-120..=60)
+            .map(|i| ceiling * 10.0_f32.powf(i as f32 / 200.0))
+            .collect();
+        let reduction: Vec<f32> = levels
+            .iter()
+            .map(|&l| -20.0 * knee_gain(l, ceiling, 12.0).log10())
+            .collect();
+        assert_eq!(reduction[0], 0.0);
+        assert!(reduction[60] > 0.0 && reduction[60] < 6.0);
+        assert!(reduction
+            .windows(2)
+            .all(|w| w[1] >= w[0] && w[1] - w[0] < 0.11));
+        assert!(levels
+            .iter()
+            .all(|&l| l * knee_gain(l, ceiling, 12.0) <= ceiling * 1.0001));
+
+        // The hard knee jumps in at the ceiling
+        assert_eq!(knee_gain(ceiling * 0.99, ceiling, 0.0), 1.0);
+        assert!((knee_gain(ceiling * 2.0, ceiling, 0.0) - 0.5).abs() < 1e-6);
+    }
+}
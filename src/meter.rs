@@ -0,0 +1,179 @@
+use crate::envelope::{flush_denormal, smoothing_coeff};
+
+/// Integration time of the correlation meter, about what hardware phase meters use.
+pub const CORRELATION_TIME_MS: f32 = 300.0;
+
+/// Running correlation between two channels, `+1` when they're identical, `-1` when one is the
+/// inverse of the other and around `0` when they're unrelated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrelationMeter {
+    coeff: f32,
+    product: f32,
+    left_power: f32,
+    right_power: f32,
+}
+
+impl CorrelationMeter {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            coeff: smoothing_coeff(CORRELATION_TIME_MS, sample_rate),
+            product: 0.0,
+            left_power: 0.0,
+            right_power: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.product = 0.0;
+        self.left_power = 0.0;
+        self.right_power = 0.0;
+    }
+
+    /// Feed one stereo sample.
+    pub fn next(&mut self, left: f32, right: f32) {
+        let smooth = |state: f32, value: f32| flush_denormal(state + self.coeff * (value - state));
+        self.product = smooth(self.product, left * right);
+        self.left_power = smooth(self.left_power, left * left);
+        self.right_power = smooth(self.right_power, right * right);
+    }
+
+    /// The current correlation, `0` while either channel is silent.
+    pub fn value(&self) -> f32 {
+        let power = (self.left_power * self.right_power).sqrt();
+        if power > 0.0 {
+            (self.product / power).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Oversampling factor of the true peak meter, what ITU-R BS.1770 asks for at 48 kHz.
+pub const TRUE_PEAK_OVERSAMPLE: usize = 4;
+/// Length of the interpolation filter, four input samples on either side of each point.
+const TRUE_PEAK_TAPS: usize = 8;
+
+/// Sample peak of a signal upsampled with a windowed sinc, to catch the overshoots between
+/// samples that a DAC's reconstruction filter produces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TruePeakMeter {
+    /// Interpolation filter for each point between two input samples.
+    phases: [[f32; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLE],
+    history: [f32; TRUE_PEAK_TAPS],
+    peak: f32,
+}
+
+impl Default for TruePeakMeter {
+    fn default() -> Self {
+        let mut phases = [[0.0; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLE];
+        for (k, phase) in phases.iter_mut().enumerate() {
+            let offset = k as f32 / TRUE_PEAK_OVERSAMPLE as f32;
+            for (j, tap) in phase.iter_mut().enumerate() {
+                // Distance from the interpolated point, which sits between the middle two taps
+                let x = j as f32 - (TRUE_PEAK_TAPS / 2 - 1) as f32 - offset;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x)
+                };
+                let half_width = (TRUE_PEAK_TAPS / 2) as f32;
+                let hann = 0.5 * (1.0 + (std::f32::consts::PI * x / half_width).cos());
+                *tap = sinc * hann;
+            }
+        }
+        Self {
+            phases,
+            history: [0.0; TRUE_PEAK_TAPS],
+            peak: 0.0,
+        }
+    }
+}
+
+impl TruePeakMeter {
+    pub fn reset(&mut self) {
+        self.history = [0.0; TRUE_PEAK_TAPS];
+        self.peak = 0.0;
+    }
+
+    /// Feed one sample. The interpolated points lag the input by half the filter length.
+    pub fn next(&mut self, sample: f32) {
+        self.history.rotate_left(1);
+        self.history[TRUE_PEAK_TAPS - 1] = sample;
+        for phase in &self.phases {
+            let value: f32 = phase.iter().zip(&self.history).map(|(a, b)| a * b).sum();
+            self.peak = self.peak.max(value.abs());
+        }
+    }
+
+    /// Highest absolute value since the last `reset_peak`.
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// Start a new measurement while keeping the filter history.
+    pub fn reset_peak(&mut self) {
+        self.peak = 0.0;
+    }
+}
+
+/// Scale every channel of a rendered buffer by the same gain so its sample peak lands on
+/// `target_db` dBFS, which keeps the crest factor and the balance between channels. Returns the
+/// gain that was applied, silence is left alone.
+pub fn normalize_peak(channels: &mut [&mut [f32]], target_db: f32) -> f32 {
+    let peak = channels
+        .iter()
+        .flat_map(|channel| channel.iter())
+        .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    if peak <= 0.0 {
+        return 1.0;
+    }
+    let gain = 10.0_f32.powf(target_db / 20.0) / peak;
+    for sample in channels.iter_mut().flat_map(|channel| channel.iter_mut()) {
+        *sample *= gain;
+    }
+    gain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift32;
+
+    #[test]
+    fn test_correlation_meter() {
+        let sample_rate = 44100.0;
+        let measure = |right: &dyn Fn(f32, f32) -> f32| {
+            let mut meter = CorrelationMeter::new(sample_rate);
+            let mut rng = XorShift32::new(1);
+            for i in 0..44100 {
+                let left = (2.0 * std::f32::consts::PI * 100.0 * i as f32 / sample_rate).sin();
+                meter.next(left, right(left, rng.next_tpdf()));
+            }
+            meter.value()
+        };
+        assert!((measure(&|left, _| left) - 1.0).abs() < 1e-3);
+        assert!((measure(&|left, _| -0.5 * left) + 1.0).abs() < 1e-3);
+        assert!(measure(&|_, noise| noise).abs() < 0.1);
+        assert_eq!(CorrelationMeter::new(sample_rate).value(), 0.0);
+    }
+
+    #[test]
+    fn test_true_peak_meter_finds_intersample_peaks() {
+        // A quarter of the sample rate at 45 degrees only ever gets sampled at 0.707 of its peak
+        let mut meter = TruePeakMeter::default();
+        for i in 0..256 {
+            let phase = std::f32::consts::FRAC_PI_4 + std::f32::consts::FRAC_PI_2 * i as f32;
+            meter.next(0.5 * phase.sin());
+        }
+        assert!((meter.peak() - 0.5).abs() < 0.02);
+
+        // A slow sine is sampled close to its peak anyway
+        let mut meter = TruePeakMeter::default();
+        for i in 0..4410 {
+            meter.next(0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin());
+        }
+        assert!((meter.peak() - 0.5).abs() < 0.01);
+        meter.reset_peak();
+        assert_eq!(meter.peak(), 0.0);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::wave::Oscillator;
+
+/// Signal a modulation route reads from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModSource {
+    /// The detection envelope, after the gate.
+    Envelope,
+    /// Bipolar sine LFO running at `ModMatrix::lfo_hz`, or once every `ModMatrix::lfo_bars` bars
+    /// while synced to the host.
+    Lfo,
+    /// Last value received for this MIDI CC, from `0` to `1`.
+    MidiCc(u8),
+}
+
+/// Parameter a modulation route writes to. The amount is in the destination's units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModDestination {
+    /// Pitch offset in octaves.
+    Pitch,
+    /// Offset added to the (linear) post gain.
+    Gain,
+    /// Extra drive into the saturator, 1.0 doubles the level going into it.
+    Drive,
+    /// Offset of the sub's lowpass cutoff in octaves.
+    Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub amount: f32,
+    /// Flip the source before scaling it. Unipolar sources (the envelope and CCs) read as
+    /// `1 - value`, so an inverted envelope sits at the full amount in silence and falls as the
+    /// input gets louder. The LFO is negated.
+    pub invert: bool,
+}
+
+impl ModRoute {
+    pub fn new(source: ModSource, destination: ModDestination, amount: f32) -> Self {
+        Self {
+            source,
+            destination,
+            amount,
+            invert: false,
+        }
+    }
+
+    /// The same route with its source inverted.
+    pub fn inverted(self) -> Self {
+        Self {
+            invert: true,
+            ..self
+        }
+    }
+}
+
+/// Current value of every modulation source for one sample.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ModValues {
+    pub envelope: f32,
+    pub lfo: f32,
+}
+
+impl ModValues {
+    /// The value of a per sample source. CCs only change with MIDI events, so `ModMatrix` keeps
+    /// them instead and they read as `0` here.
+    pub fn get(&self, source: ModSource) -> f32 {
+        match source {
+            ModSource::Envelope => self.envelope,
+            ModSource::Lfo => self.lfo,
+            ModSource::MidiCc(_) => 0.0,
+        }
+    }
+}
+
+/// Length of one bar in seconds at `tempo` BPM in a `numerator`/`denominator` meter. The tempo
+/// counts quarter notes, so a 6/8 bar lasts three beats.
+pub fn bar_length_secs(tempo: f64, numerator: i32, denominator: i32) -> f64 {
+    let quarter_notes = numerator as f64 * 4.0 / denominator.max(1) as f64;
+    quarter_notes * 60.0 / tempo
+}
+
+/// List of routes from modulation sources to destinations, evaluated once per sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModMatrix {
+    pub routes: Vec<ModRoute>,
+    pub lfo_hz: f32,
+    /// Length of one LFO cycle in bars. When set, `sync_lfo` derives `lfo_hz` from the host's
+    /// tempo and meter.
+    pub lfo_bars: Option<f32>,
+    lfo: Oscillator,
+    /// Last value of every MIDI CC.
+    cc: [f32; 128],
+}
+
+impl Default for ModMatrix {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            lfo_hz: 1.0,
+            lfo_bars: None,
+            lfo: Oscillator::default(),
+            cc: [0.0; 128],
+        }
+    }
+}
+
+impl ModMatrix {
+    pub fn add_route(&mut self, route: ModRoute) {
+        self.routes.push(route);
+    }
+
+    pub fn reset(&mut self) {
+        self.lfo = Oscillator::default();
+        self.cc = [0.0; 128];
+    }
+
+    /// Store a CC value from `0` to `1` for the routes reading from `ModSource::MidiCc(cc)`.
+    pub fn set_cc(&mut self, cc: u8, value: f32) {
+        if let Some(stored) = self.cc.get_mut(cc as usize) {
+            *stored = value;
+        }
+    }
+
+    /// Update `lfo_hz` from the host's tempo and time signature, if the LFO is synced.
+    pub fn sync_lfo(&mut self, tempo: f64, numerator: i32, denominator: i32) {
+        if let Some(bars) = self.lfo_bars {
+            let period = bars as f64 * bar_length_secs(tempo, numerator, denominator);
+            if period > 0.0 {
+                self.lfo_hz = (1.0 / period) as f32;
+            }
+        }
+    }
+
+    /// Advance the LFO by one sample and return the source values for that sample.
+    pub fn next(&mut self, envelope: f32, sample_rate: f32) -> ModValues {
+        ModValues {
+            envelope,
+            lfo: self.lfo.next(self.lfo_hz, sample_rate).sin(),
+        }
+    }
+
+    /// Sum of all routes to `destination`.
+    pub fn modulation(&self, destination: ModDestination, values: &ModValues) -> f32 {
+        self.routes
+            .iter()
+            .filter(|route| route.destination == destination)
+            .map(|route| {
+                let value = match route.source {
+                    ModSource::MidiCc(cc) => self.cc.get(cc as usize).copied().unwrap_or(0.0),
+                    source => values.get(source),
+                };
+                let value = match (route.invert, route.source) {
+                    (false, _) => value,
+                    (true, ModSource::Lfo) => -value,
+                    (true, _) => 1.0 - value,
+                };
+                route.amount * value
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mod_matrix_sums_routes_per_destination() {
+        let mut matrix = ModMatrix::default();
+        matrix.add_route(ModRoute::new(ModSource::Envelope, ModDestination::Pitch, 0.5));
+        matrix.add_route(ModRoute::new(ModSource::Lfo, ModDestination::Pitch, 0.25));
+        matrix.add_route(ModRoute::new(ModSource::Envelope, ModDestination::Gain, -1.0));
+        let values = ModValues {
+            envelope: 0.8,
+            lfo: -1.0,
+        };
+        assert!((matrix.modulation(ModDestination::Pitch, &values) - 0.15).abs() < 1e-6);
+        assert_eq!(matrix.modulation(ModDestination::Gain, &values), -0.8);
+        assert_eq!(matrix.modulation(ModDestination::Drive, &values), 0.0);
+    }
+
+    #[test]
+    fn test_mod_matrix_lfo_is_bipolar_sine() {
+        let mut matrix = ModMatrix {
+            lfo_hz: 1.0,
+            ..ModMatrix::default()
+        };
+        let lfo: Vec<f32> = (0..4).map(|_| matrix.next(0.0, 4.0).lfo).collect();
+        let expected = [0.0, 1.0, 0.0, -1.0];
+        assert!(lfo.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_mod_matrix_lfo_syncs_to_bars_in_six_eight() {
+        // A 6/8 bar at 120 BPM is three quarter notes, 1.5 seconds
+        assert!((bar_length_secs(120.0, 6, 8) - 1.5).abs() < 1e-9);
+        let mut matrix = ModMatrix {
+            lfo_bars: Some(1.0),
+            ..ModMatrix::default()
+        };
+        matrix.sync_lfo(120.0, 6, 8);
+        assert!((1.0 / matrix.lfo_hz - 1.5).abs() < 1e-6);
+
+        // Unsynced LFOs keep their rate
+        let mut free = ModMatrix::default();
+        free.sync_lfo(120.0, 6, 8);
+        assert_eq!(free.lfo_hz, 1.0);
+    }
+
+    #[test]
+    fn test_mod_matrix_midi_cc_depth() {
+        let mut matrix = ModMatrix::default();
+        matrix.add_route(ModRoute::new(ModSource::MidiCc(1), ModDestination::Pitch, 2.0));
+        matrix.add_route(ModRoute::new(ModSource::MidiCc(7), ModDestination::Gain, -0.5));
+        let values = ModValues::default();
+        assert_eq!(matrix.modulation(ModDestination::Pitch, &values), 0.0);
+
+        matrix.set_cc(1, 0.25);
+        matrix.set_cc(7, 1.0);
+        assert_eq!(matrix.modulation(ModDestination::Pitch, &values), 0.5);
+        assert_eq!(matrix.modulation(ModDestination::Gain, &values), -0.5);
+
+        // CCs past 127 don't exist and are ignored
+        matrix.set_cc(200, 1.0);
+        matrix.reset();
+        assert_eq!(matrix.modulation(ModDestination::Pitch, &values), 0.0);
+    }
+
+    #[test]
+    fn test_mod_matrix_inverted_route_moves_opposite() {
+        let mut matrix = ModMatrix::default();
+        matrix.add_route(ModRoute::new(ModSource::Envelope, ModDestination::Pitch, 1.0));
+        matrix.add_route(ModRoute::new(ModSource::Envelope, ModDestination::Gain, 1.0).inverted());
+        matrix.add_route(ModRoute::new(ModSource::Lfo, ModDestination::Drive, 0.5).inverted());
+
+        let at = |envelope: f32| {
+            let values = ModValues { envelope, lfo: 1.0 };
+            (
+                matrix.modulation(ModDestination::Pitch, &values),
+                matrix.modulation(ModDestination::Gain, &values),
+            )
+        };
+        let (quiet_pitch, quiet_gain) = at(0.0);
+        let (loud_pitch, loud_gain) = at(0.75);
+        assert_eq!((quiet_pitch, quiet_gain), (0.0, 1.0));
+        assert!(loud_pitch > quiet_pitch);
+        assert!(loud_gain < quiet_gain);
+        assert_eq!(loud_gain, 0.25);
+
+        let values = ModValues { envelope: 0.0, lfo: 1.0 };
+        assert_eq!(matrix.modulation(ModDestination::Drive, &values), -0.5);
+    }
+}
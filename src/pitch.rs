@@ -0,0 +1,376 @@
+use nih_plug::prelude::Enum;
+
+/// Scales available for pitch quantization.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    #[name = "Major Pentatonic"]
+    MajorPentatonic,
+    #[name = "Minor Pentatonic"]
+    MinorPentatonic,
+}
+
+impl Scale {
+    /// Semitone offsets from the root that belong to the scale.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+}
+
+pub fn freq_to_note(freq: f32) -> f32 {
+    69.0 + 12.0 * (freq / 440.0).log2()
+}
+
+pub fn note_to_freq(note: f32) -> f32 {
+    440.0 * 2.0_f32.powf((note - 69.0) / 12.0)
+}
+
+/// Frequency ratio of an interval of `cents`, 100 cents make a semitone.
+pub fn cents_to_ratio(cents: f32) -> f32 {
+    2.0_f32.powf(cents / 1200.0)
+}
+
+/// Snap `freq` to the nearest note whose pitch class relative to `root` (0 = C) is in `scale`.
+pub fn quantize_pitch(freq: f32, scale: &[u8], root: u8) -> f32 {
+    if scale.is_empty() || freq <= 0.0 {
+        return freq;
+    }
+
+    let note = freq_to_note(freq);
+    let center = note.round() as i32;
+    let mut best: Option<i32> = None;
+    for candidate in (center - 12)..=(center + 12) {
+        let degree = (candidate - root as i32).rem_euclid(12) as u8;
+        if !scale.contains(&degree) {
+            continue;
+        }
+        let closer = match best {
+            Some(b) => (candidate as f32 - note).abs() < (b as f32 - note).abs(),
+            None => true,
+        };
+        if closer {
+            best = Some(candidate);
+        }
+    }
+
+    match best {
+        Some(n) => note_to_freq(n as f32),
+        None => freq,
+    }
+}
+
+/// Lowest frequency `detect_pitch` looks for.
+pub const MIN_DETECT_HZ: f32 = 40.0;
+/// Highest frequency `detect_pitch` looks for.
+pub const MAX_DETECT_HZ: f32 = 1000.0;
+/// Dips of the normalized difference function below this count as a period.
+const YIN_THRESHOLD: f32 = 0.15;
+
+/// Estimate the fundamental frequency of `samples` with the YIN algorithm. `samples` should hold
+/// at least two periods of the lowest frequency of interest. Returns `None` for silence and
+/// unpitched input.
+pub fn detect_pitch(samples: &[f32], sample_rate: f32) -> Option<f32> {
+    let window = samples.len() / 2;
+    let min_lag = ((sample_rate / MAX_DETECT_HZ) as usize).max(2);
+    let max_lag = ((sample_rate / MIN_DETECT_HZ).ceil() as usize).min(window);
+    if min_lag + 1 >= max_lag || samples[..window].iter().all(|&s| s.abs() < 1e-6) {
+        return None;
+    }
+
+    let difference = |lag: usize| -> f32 {
+        samples[..window]
+            .iter()
+            .zip(&samples[lag..lag + window])
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum()
+    };
+
+    // Cumulative mean normalized difference, computed lag by lag so this doesn't allocate
+    let mut running_sum = 0.0;
+    let mut normalized = |lag: usize| {
+        let d = difference(lag);
+        running_sum += d;
+        if running_sum > 0.0 {
+            d * lag as f32 / running_sum
+        } else {
+            1.0
+        }
+    };
+    let mut prev = normalized(1);
+    let mut lag = 2;
+    while lag < max_lag {
+        let current = normalized(lag);
+        if lag >= min_lag && current < YIN_THRESHOLD {
+            // Walk down to the bottom of the dip and refine it with a parabola
+            let mut next = normalized(lag + 1);
+            let mut current = current;
+            while next < current && lag + 1 < max_lag {
+                prev = current;
+                current = next;
+                lag += 1;
+                next = normalized(lag + 1);
+            }
+            let denom = prev - 2.0 * current + next;
+            let offset = if denom.abs() > f32::EPSILON {
+                (0.5 * (prev - next) / denom).clamp(-0.5, 0.5)
+            } else {
+                0.0
+            };
+            return Some(sample_rate / (lag as f32 + offset));
+        }
+        prev = current;
+        lag += 1;
+    }
+
+    None
+}
+
+/// Tracks how far the input's sine component is ahead of an oscillator, by correlating the input
+/// with the oscillator's sine and cosine and smoothing the products.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhaseDetector {
+    coeff: f32,
+    re: f32,
+    im: f32,
+}
+
+impl PhaseDetector {
+    pub fn new(time_ms: f32, sample_rate: f32) -> Self {
+        Self {
+            coeff: crate::envelope::smoothing_coeff(time_ms, sample_rate),
+            re: 0.0,
+            im: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.re = 0.0;
+        self.im = 0.0;
+    }
+
+    /// Feed one input sample along with the oscillator's phase for it, and return the phase
+    /// difference in radians in `-π..=π`. Silence reads as no difference.
+    pub fn next(&mut self, sample: f32, phase: f32) -> f32 {
+        let (sin, cos) = phase.sin_cos();
+        self.re += self.coeff * (sample * sin - self.re);
+        self.im += self.coeff * (sample * cos - self.im);
+        self.im.atan2(self.re)
+    }
+}
+
+/// Runs `detect_pitch` over a sliding window of the incoming audio, so the detection doesn't
+/// depend on the host's block size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitchTracker {
+    history: Vec<f32>,
+    hop: usize,
+    since_detect: usize,
+    pitch: Option<f32>,
+}
+
+impl PitchTracker {
+    pub fn new(sample_rate: f32) -> Self {
+        let window = (2.0 * sample_rate / MIN_DETECT_HZ).ceil() as usize + 2;
+        Self {
+            history: vec![0.0; window],
+            hop: window / 2,
+            since_detect: 0,
+            pitch: None,
+        }
+    }
+
+    /// The most recent estimate.
+    pub fn pitch(&self) -> Option<f32> {
+        self.pitch
+    }
+
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.since_detect = 0;
+        self.pitch = None;
+    }
+
+    /// Add a block of samples and return the current estimate. The detection reruns once per
+    /// half window.
+    pub fn process(&mut self, samples: &[f32], sample_rate: f32) -> Option<f32> {
+        let window = self.history.len();
+        if samples.len() >= window {
+            self.history.copy_from_slice(&samples[samples.len() - window..]);
+        } else {
+            self.history.copy_within(samples.len().., 0);
+            self.history[window - samples.len()..].copy_from_slice(samples);
+        }
+
+        self.since_detect += samples.len();
+        if self.since_detect >= self.hop {
+            self.since_detect = 0;
+            self.pitch = detect_pitch(&self.history, sample_rate);
+        }
+        self.pitch
+    }
+}
+
+/// Path a glide takes from one pitch to the next.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GlideCurve {
+    /// Equal steps in Hz, lingers in the low end when gliding down.
+    Linear,
+    /// Equal steps in cents, so every octave takes the same time.
+    #[default]
+    Exponential,
+}
+
+/// Portamento between successive target frequencies. A new target starts a glide from wherever
+/// the previous one had got to.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Glide {
+    from: f32,
+    to: f32,
+    current: f32,
+    pos: u32,
+    len: u32,
+}
+
+impl Glide {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Advance by one sample toward `target`, taking `glide_samples` to get there from the
+    /// current frequency. The very first target is played right away.
+    pub fn next(&mut self, target: f32, glide_samples: u32, curve: GlideCurve) -> f32 {
+        if self.current <= 0.0 {
+            self.current = target;
+            self.to = target;
+        }
+        if target != self.to {
+            self.from = self.current;
+            self.to = target;
+            self.pos = 0;
+            self.len = glide_samples;
+        }
+        if self.pos >= self.len {
+            self.current = self.to;
+        } else {
+            self.pos += 1;
+            let t = self.pos as f32 / self.len as f32;
+            self.current = match curve {
+                GlideCurve::Linear => self.from + t * (self.to - self.from),
+                GlideCurve::Exponential => self.from * (self.to / self.from).powf(t),
+            };
+        }
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glide_curve_midpoints() {
+        let glide_to_400 = |curve: GlideCurve| {
+            let mut glide = Glide::default();
+            assert_eq!(glide.next(100.0, 100, curve), 100.0);
+            let path: Vec<f32> = (0..100).map(|_| glide.next(400.0, 100, curve)).collect();
+            path
+        };
+
+        // Halfway through, an exponential glide sits on the geometric mean and a linear one on
+        // the arithmetic mean
+        let exponential = glide_to_400(GlideCurve::Exponential);
+        assert!((exponential[49] - 200.0).abs() < 1e-3);
+        assert_eq!(exponential[99], 400.0);
+        let linear = glide_to_400(GlideCurve::Linear);
+        assert!((linear[49] - 250.0).abs() < 1e-3);
+        assert_eq!(linear[99], 400.0);
+
+        // Without a glide time the target is played right away
+        let mut glide = Glide::default();
+        glide.next(100.0, 0, GlideCurve::Exponential);
+        assert_eq!(glide.next(400.0, 0, GlideCurve::Exponential), 400.0);
+    }
+
+    #[test]
+    fn test_cents_to_ratio() {
+        assert_eq!(cents_to_ratio(0.0), 1.0);
+        assert!((cents_to_ratio(100.0) - 2.0_f32.powf(1.0 / 12.0)).abs() < 1e-6);
+        assert!((cents_to_ratio(-1200.0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_quantize_chromatic_snaps_to_a4() {
+        let freq = quantize_pitch(445.0, Scale::Chromatic.intervals(), 0);
+        assert!((freq - 440.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_quantize_major_skips_out_of_scale_notes() {
+        // C#4 is not in C major, the closest allowed notes are C4 and D4
+        let c_sharp = note_to_freq(61.0);
+        let freq = quantize_pitch(c_sharp * 1.01, Scale::Major.intervals(), 0);
+        assert!((freq - note_to_freq(62.0)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_quantize_respects_root() {
+        // A minor pentatonic contains A, C, D, E and G
+        let freq = quantize_pitch(note_to_freq(70.0), Scale::MinorPentatonic.intervals(), 9);
+        assert!((freq - note_to_freq(69.0)).abs() < 1e-2);
+    }
+
+    fn sine(freq: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_pitch_sine() {
+        for freq in [55.0, 110.0, 220.0, 440.0] {
+            let detected = detect_pitch(&sine(freq, 44100.0, 2400), 44100.0).unwrap();
+            assert!((detected - freq).abs() < freq * 0.005, "{freq}: {detected}");
+        }
+    }
+
+    #[test]
+    fn test_detect_pitch_silence_and_noise() {
+        assert_eq!(detect_pitch(&[0.0; 2400], 44100.0), None);
+        let mut rng = crate::rng::XorShift32::default();
+        let noise: Vec<f32> = (0..2400).map(|_| rng.next_tpdf()).collect();
+        assert_eq!(detect_pitch(&noise, 44100.0), None);
+    }
+
+    #[test]
+    fn test_phase_detector() {
+        let omega = 2.0 * std::f32::consts::PI * 100.0 / 44100.0;
+        for offset in [-3.0, -1.0, 0.0, 0.5, 2.5] {
+            let mut detector = PhaseDetector::new(20.0, 44100.0);
+            let mut error = 0.0;
+            for i in 0..44100 {
+                let phase = i as f32 * omega;
+                error = detector.next((phase + offset).sin(), phase);
+            }
+            assert!((error - offset).abs() < 0.1, "{offset}: {error}");
+        }
+        let mut detector = PhaseDetector::new(20.0, 44100.0);
+        assert_eq!(detector.next(0.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_pitch_tracker_small_blocks() {
+        let mut tracker = PitchTracker::new(44100.0);
+        for block in sine(110.0, 44100.0, 8192).chunks(64) {
+            tracker.process(block, 44100.0);
+        }
+        assert!((tracker.pitch().unwrap() - 110.0).abs() < 0.5);
+    }
+}
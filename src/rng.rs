@@ -0,0 +1,122 @@
+/// Small xorshift PRNG for noise and dither. Not suitable for anything security related, but
+/// it's fast, allocation free and deterministic for a given seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl Default for XorShift32 {
+    fn default() -> Self {
+        Self::new(0x2545_f491)
+    }
+}
+
+impl XorShift32 {
+    /// Create a generator from `seed`. A zero seed would get stuck at zero, so it's replaced.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { 0x2545_f491 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Uniformly distributed value in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Triangular (TPDF) distributed value in `(-1, 1)`, the sum of two uniform values.
+    pub fn next_tpdf(&mut self) -> f32 {
+        self.next_f32() - self.next_f32()
+    }
+}
+
+/// Slow random signal in `[-1, 1]`. A new random value is picked `rate_hz` times a second and
+/// the output eases from one to the next, so it wanders without any steps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothNoise {
+    rng: XorShift32,
+    from: f32,
+    to: f32,
+    pos: f32,
+}
+
+impl Default for SmoothNoise {
+    fn default() -> Self {
+        Self::new(XorShift32::default())
+    }
+}
+
+impl SmoothNoise {
+    /// Start at zero, heading for the first value from `rng`.
+    pub fn new(mut rng: XorShift32) -> Self {
+        let to = rng.next_f32() * 2.0 - 1.0;
+        Self {
+            rng,
+            from: 0.0,
+            to,
+            pos: 0.0,
+        }
+    }
+
+    pub fn next(&mut self, rate_hz: f32, sample_rate: f32) -> f32 {
+        self.pos += rate_hz / sample_rate;
+        if self.pos >= 1.0 {
+            self.pos -= 1.0;
+            self.from = self.to;
+            self.to = self.rng.next_f32() * 2.0 - 1.0;
+        }
+        // Smoothstep, so the slope is zero at every value it passes through
+        let t = self.pos * self.pos * (3.0 - 2.0 * self.pos);
+        self.from + t * (self.to - self.from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smooth_noise_is_slow_and_bounded() {
+        let mut noise = SmoothNoise::new(XorShift32::new(7));
+        let values: Vec<f32> = (0..44100).map(|_| noise.next(2.0, 44100.0)).collect();
+        assert!(values.iter().all(|v| (-1.0..=1.0).contains(v)));
+        // A step of 2 over half a second can't move more than 3 / 22050 per sample
+        assert!(values.windows(2).all(|pair| (pair[1] - pair[0]).abs() <= 1.5e-4));
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(max - min > 0.1);
+    }
+
+    #[test]
+    fn test_xorshift_deterministic() {
+        let mut a = XorShift32::new(1234);
+        let mut b = XorShift32::new(1234);
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+        assert_ne!(XorShift32::new(1).next_u32(), XorShift32::new(2).next_u32());
+    }
+
+    #[test]
+    fn test_xorshift_ranges() {
+        let mut rng = XorShift32::new(0);
+        let mut sum = 0.0;
+        for _ in 0..10000 {
+            let v = rng.next_f32();
+            assert!((0.0..1.0).contains(&v));
+            let t = rng.next_tpdf();
+            assert!(t > -1.0 && t < 1.0);
+            sum += t;
+        }
+        assert!((sum / 10000.0).abs() < 0.02);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::filter::Biquad;
+use nih_plug::prelude::Enum;
+
+/// Curve of the drive stage's clipper.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaturationType {
+    /// Smooth and symmetric, the classic soft clip.
+    #[default]
+    Tanh,
+    /// Softer knee than `Tanh` that approaches full scale more slowly.
+    Arctan,
+    /// Third order polynomial that reaches full scale at 1.5, only adds odd harmonics.
+    Cubic,
+    /// Flat cut at full scale, the harshest option.
+    #[name = "Hard Clip"]
+    HardClip,
+}
+
+/// Clipper used for the drive stage. Every type has unity gain for small signals and is bounded
+/// to ±1 for large ones.
+pub fn saturate(sample: f32, kind: SaturationType) -> f32 {
+    match kind {
+        SaturationType::Tanh => sample.tanh(),
+        SaturationType::Arctan => {
+            std::f32::consts::FRAC_2_PI * (std::f32::consts::FRAC_PI_2 * sample).atan()
+        }
+        SaturationType::Cubic => {
+            let sample = sample.clamp(-1.5, 1.5);
+            sample - 4.0 / 27.0 * sample * sample * sample
+        }
+        SaturationType::HardClip => sample.clamp(-1.0, 1.0),
+    }
+}
+
+/// Wavefolder, `sample` is boosted by `1 + amount` and everything past ±1 is reflected back
+/// inside, as often as it takes. Unlike a clipper every fold adds zero crossings, so the
+/// harmonics keep growing with `amount`. Signals within ±1 pass unchanged at `amount` `0`.
+pub fn wavefold(sample: f32, amount: f32) -> f32 {
+    // A triangle with a period of 4 that is the identity between -1 and 1
+    let t = (sample * (1.0 + amount.max(0.0)) + 1.0).rem_euclid(4.0);
+    if t < 2.0 {
+        t - 1.0
+    } else {
+        3.0 - t
+    }
+}
+
+/// Boost of the pre-emphasis above its frequency, the de-emphasis cuts by the same amount.
+pub const EMPHASIS_DB: f32 = 12.0;
+
+/// Matched high shelf boost before and cut after a waveshaper. The saturation mostly hits the
+/// content above the shelf while the linear part of the signal passes through unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Emphasis {
+    pre: Biquad,
+    de: Biquad,
+}
+
+impl Emphasis {
+    pub fn new(freq: f32, sample_rate: f32) -> Self {
+        Self {
+            pre: Biquad::high_shelf(freq, EMPHASIS_DB, sample_rate),
+            de: Biquad::high_shelf(freq, -EMPHASIS_DB, sample_rate),
+        }
+    }
+
+    /// Update the shelf frequency while keeping the filter state.
+    pub fn set_frequency(&mut self, freq: f32, sample_rate: f32) {
+        self.pre.set_high_shelf(freq, EMPHASIS_DB, sample_rate);
+        self.de.set_high_shelf(freq, -EMPHASIS_DB, sample_rate);
+    }
+
+    pub fn reset(&mut self) {
+        self.pre.reset();
+        self.de.reset();
+    }
+
+    /// Run `sample` through the pre-emphasis, `shaper` and the de-emphasis.
+    pub fn process(&mut self, sample: f32, shaper: impl FnOnce(f32) -> f32) -> f32 {
+        self.de.process(shaper(self.pre.process(sample)))
+    }
+}
+
+/// How many times faster than the host rate the oversampled shaper runs.
+pub const OVERSAMPLE_FACTOR: usize = 4;
+
+/// Q of the four sections of an eighth order Butterworth low-pass.
+const BUTTERWORTH_8_Q: [f32; 4] = [0.509_795_6, 0.601_344_9, 0.899_976_2, 2.562_915_4];
+
+/// Runs a waveshaper at `OVERSAMPLE_FACTOR` times the sample rate, so the harmonics it adds
+/// above the host's Nyquist frequency are filtered out instead of folding back down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oversampler {
+    up: [Biquad; 4],
+    down: [Biquad; 4],
+}
+
+impl Oversampler {
+    pub fn new(sample_rate: f32) -> Self {
+        let oversampled_rate = sample_rate * OVERSAMPLE_FACTOR as f32;
+        let lowpass =
+            BUTTERWORTH_8_Q.map(|q| Biquad::lowpass(0.45 * sample_rate, q, oversampled_rate));
+        Self {
+            up: lowpass,
+            down: lowpass,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.up.iter_mut().chain(self.down.iter_mut()) {
+            filter.reset();
+        }
+    }
+
+    /// Upsample `sample`, run every oversampled sample through `shaper` and decimate back down.
+    pub fn process(&mut self, sample: f32, mut shaper: impl FnMut(f32) -> f32) -> f32 {
+        let mut out = 0.0;
+        for k in 0..OVERSAMPLE_FACTOR {
+            // Zero stuffing, the gain makes up for the inserted zeros
+            let stuffed = if k == 0 {
+                sample * OVERSAMPLE_FACTOR as f32
+            } else {
+                0.0
+            };
+            let upsampled = self.up.iter_mut().fold(stuffed, |s, f| f.process(s));
+            let shaped = self
+                .down
+                .iter_mut()
+                .fold(shaper(upsampled), |s, f| f.process(s));
+            if k == 0 {
+                out = shaped;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::magnitude_at;
+
+    #[test]
+    fn test_saturate_bounded_and_unity_at_small_signals() {
+        use SaturationType::*;
+        for kind in [Tanh, Arctan, Cubic, HardClip] {
+            assert!((saturate(0.001, kind) - 0.001).abs() < 1e-6);
+            for sample in [1.0, 1.5, 2.0, 100.0] {
+                assert!(saturate(sample, kind).abs() <= 1.0);
+                assert!(saturate(-sample, kind).abs() <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_saturate_cubic_adds_odd_harmonics() {
+        let sample_rate = 44100.0;
+        let output: Vec<f32> = (0..4410)
+            .map(|i| {
+                let sine = (2.0 * std::f32::consts::PI * 100.0 * i as f32 / sample_rate).sin();
+                saturate(1.2 * sine, SaturationType::Cubic)
+            })
+            .collect();
+        let harmonic = |n: f32| magnitude_at(&output, sample_rate, 100.0 * n);
+        assert!(harmonic(3.0) > 0.05);
+        assert!(harmonic(2.0) < 1e-3 * harmonic(3.0));
+        assert!(harmonic(4.0) < 1e-3 * harmonic(3.0));
+    }
+
+    #[test]
+    fn test_wavefold_adds_folds_and_stays_bounded() {
+        assert_eq!(wavefold(0.5, 0.0), 0.5);
+        assert_eq!(wavefold(-0.25, 0.0), -0.25);
+        // 1.5 reflects off 1 down to 0.5
+        assert!((wavefold(0.75, 1.0) - 0.5).abs() < 1e-6);
+
+        let sine: Vec<f32> = (0..1000)
+            .map(|i| (2.0 * std::f32::consts::PI * i as f32 / 1000.0).sin())
+            .collect();
+        let crossings = |signal: &[f32]| {
+            signal
+                .windows(2)
+                .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+                .count()
+        };
+        let folded: Vec<f32> = sine.iter().map(|&s| wavefold(s, 4.0)).collect();
+        assert!(folded.iter().all(|s| s.abs() <= 1.0));
+        // A single cycle of the sine crosses zero once in the middle, driven five times over it
+        // folds back through zero twice on every half
+        assert_eq!(crossings(&sine), 1);
+        assert!(crossings(&folded) >= 5);
+    }
+
+    #[test]
+    fn test_emphasis_saturates_the_highs() {
+        // A loud fundamental with a quieter upper partial, its third harmonic is what the
+        // saturation adds up high
+        let sample_rate = 44100.0;
+        let input: Vec<f32> = (0..8820)
+            .map(|i| {
+                let t = 2.0 * std::f32::consts::PI * i as f32 / sample_rate;
+                0.5 * (55.0 * t).sin() + 0.2 * (880.0 * t).sin()
+            })
+            .collect();
+        let drive = |s: f32| saturate(s * 2.0, SaturationType::Tanh);
+        let flat: Vec<f32> = input.iter().map(|&s| drive(s)).collect();
+        let mut emphasis = Emphasis::new(200.0, sample_rate);
+        let emphasized: Vec<f32> = input.iter().map(|&s| emphasis.process(s, drive)).collect();
+
+        let high_ratio = |x: &[f32]| {
+            magnitude_at(&x[4410..], sample_rate, 2640.0)
+                / magnitude_at(&x[4410..], sample_rate, 55.0)
+        };
+        assert!(high_ratio(&emphasized) > 10.0 * high_ratio(&flat));
+
+        // Without a nonlinearity the two shelves cancel out
+        let mut emphasis = Emphasis::new(200.0, sample_rate);
+        for &s in &input {
+            assert!((emphasis.process(s, |x| x) - s).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_oversampler_reduces_aliasing() {
+        // The 5th, 7th and 9th harmonic of a 5 kHz sine fold back down to these frequencies
+        let sample_rate = 44100.0;
+        let aliases = [19100.0, 9100.0, 900.0];
+        let input: Vec<f32> = (0..8820)
+            .map(|i| (2.0 * std::f32::consts::PI * 5000.0 * i as f32 / sample_rate).sin())
+            .collect();
+        let drive = |s: f32| saturate(s * 4.0, SaturationType::Tanh);
+        let plain: Vec<f32> = input.iter().map(|&s| drive(s)).collect();
+        let mut oversampler = Oversampler::new(sample_rate);
+        let oversampled: Vec<f32> = input
+            .iter()
+            .map(|&s| oversampler.process(s, drive))
+            .collect();
+
+        let alias_level = |x: &[f32]| -> f32 {
+            aliases
+                .iter()
+                .map(|&freq| magnitude_at(&x[4410..], sample_rate, freq))
+                .sum()
+        };
+        assert!(alias_level(&oversampled) < 0.25 * alias_level(&plain));
+        // The fundamental passes through at the same level
+        let fundamental = magnitude_at(&plain[4410..], sample_rate, 5000.0);
+        assert!(
+            (magnitude_at(&oversampled[4410..], sample_rate, 5000.0) - fundamental).abs() < 0.05
+        );
+    }
+}
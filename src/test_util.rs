@@ -0,0 +1,37 @@
+//! Spectral helpers shared by the unit tests.
+
+/// Magnitude of the DFT of `samples` at `freq`, scaled so a full scale sine reads as 1.0.
+pub fn magnitude_at(samples: &[f32], sample_rate: f32, freq: f32) -> f32 {
+    let (mut re, mut im) = (0.0_f64, 0.0_f64);
+    for (i, &s) in samples.iter().enumerate() {
+        let w = 2.0 * std::f64::consts::PI * freq as f64 * i as f64 / sample_rate as f64;
+        re += s as f64 * w.cos();
+        im -= s as f64 * w.sin();
+    }
+    (re.hypot(im) * 2.0 / samples.len() as f64) as f32
+}
+
+/// Summed squared magnitude of all DFT bins between `min_hz` and `max_hz`.
+pub fn band_energy(samples: &[f32], sample_rate: f32, min_hz: f32, max_hz: f32) -> f32 {
+    let bin_width = sample_rate / samples.len() as f32;
+    let first = (min_hz / bin_width).ceil() as usize;
+    let last = ((max_hz / bin_width).floor() as usize).min(samples.len() / 2);
+    (first..=last)
+        .map(|k| magnitude_at(samples, sample_rate, k as f32 * bin_width).powi(2))
+        .sum()
+}
+
+/// Pearson correlation coefficient of two equally long signals.
+pub fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean = |x: &[f32]| x.iter().sum::<f32>() / x.len() as f32;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (&x, &y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    cov / (var_a * var_b).sqrt().max(f32::EPSILON)
+}
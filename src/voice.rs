@@ -0,0 +1,194 @@
+use crate::adsr::Adsr;
+use crate::wave::Oscillator;
+
+/// Upper limit for the number of simultaneous MIDI voices.
+pub const MAX_VOICES: usize = 8;
+
+/// The saw resets at a phase of π. Interval oscillators start at an offset so their ramps reset
+/// together with the fundamental instead of partially cancelling it.
+fn interval_oscillator(ratio: f32) -> Oscillator {
+    Oscillator::new((ratio - 1.0) * std::f32::consts::PI)
+}
+
+/// Oscillators for a saw and the fifth and octave stacked on top of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SawStack {
+    fundamental: Oscillator,
+    fifth: Oscillator,
+    octave: Oscillator,
+}
+
+impl Default for SawStack {
+    fn default() -> Self {
+        Self {
+            fundamental: Oscillator::default(),
+            fifth: interval_oscillator(1.5),
+            octave: interval_oscillator(2.0),
+        }
+    }
+}
+
+impl SawStack {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Move the fundamental to `phase` and keep the intervals aligned with it.
+    pub fn set_phase(&mut self, phase: f32) {
+        self.fundamental = Oscillator::new(phase);
+        self.fifth = Oscillator::new(phase * 1.5 + 0.5 * std::f32::consts::PI);
+        self.octave = Oscillator::new(phase * 2.0 + std::f32::consts::PI);
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.fundamental.phase()
+    }
+
+    /// Move the fundamental by `delta` radians, and the intervals by as much of their own cycles.
+    pub fn shift(&mut self, delta: f32) {
+        self.fundamental.shift(delta);
+        self.fifth.shift(delta * 1.5);
+        self.octave.shift(delta * 2.0);
+    }
+
+    /// Phases of the fundamental, fifth and octave for the current sample. All three always
+    /// advance so they stay aligned with each other.
+    pub fn next(&mut self, freq: f32, sample_rate: f32) -> [f32; 3] {
+        [
+            self.fundamental.next(freq, sample_rate),
+            self.fifth.next(freq * 1.5, sample_rate),
+            self.octave.next(freq * 2.0, sample_rate),
+        ]
+    }
+
+    /// Like `next`, with the phases accumulated in double precision.
+    pub fn next_f64(&mut self, freq: f64, sample_rate: f64) -> [f32; 3] {
+        [
+            self.fundamental.next_f64(freq, sample_rate),
+            self.fifth.next_f64(freq * 1.5, sample_rate),
+            self.octave.next_f64(freq * 2.0, sample_rate),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voice {
+    pub note: u8,
+    pub adsr: Adsr,
+    pub saws: SawStack,
+}
+
+/// Voices for the held (and releasing) MIDI notes, ordered from oldest to newest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoicePool {
+    voices: Vec<Voice>,
+}
+
+impl Default for VoicePool {
+    fn default() -> Self {
+        Self {
+            voices: Vec::with_capacity(MAX_VOICES),
+        }
+    }
+}
+
+impl VoicePool {
+    pub fn len(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Voice> {
+        self.voices.iter_mut()
+    }
+
+    /// Start `note`, using `adsr` as the envelope for a new voice. Once `max_voices` are playing
+    /// the oldest one is taken over, its envelope restarts from the current level so it doesn't
+    /// click. This never allocates past `MAX_VOICES`.
+    pub fn note_on(&mut self, note: u8, max_voices: usize, adsr: Adsr) {
+        let max_voices = max_voices.clamp(1, MAX_VOICES);
+        let mut voice = if let Some(idx) = self.voices.iter().position(|v| v.note == note) {
+            self.voices.remove(idx)
+        } else {
+            while self.voices.len() > max_voices {
+                self.voices.remove(0);
+            }
+            if self.voices.len() == max_voices {
+                self.voices.remove(0)
+            } else {
+                Voice {
+                    note,
+                    adsr,
+                    saws: SawStack::default(),
+                }
+            }
+        };
+        voice.note = note;
+        voice.adsr.note_on();
+        self.voices.push(voice);
+    }
+
+    pub fn note_off(&mut self, note: u8, sample_rate: f32) {
+        for voice in self.voices.iter_mut().filter(|v| v.note == note) {
+            voice.adsr.note_off(sample_rate);
+        }
+    }
+
+    /// Drop the voices whose release has finished.
+    pub fn remove_finished(&mut self) {
+        self.voices.retain(|v| v.adsr.is_active());
+    }
+
+    pub fn reset(&mut self) {
+        self.voices.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(pool: &VoicePool) -> Vec<u8> {
+        pool.voices.iter().map(|v| v.note).collect()
+    }
+
+    #[test]
+    fn test_voice_pool_steals_oldest() {
+        let adsr = Adsr::new(5.0, 0.0, 1.0, 100.0);
+        let mut pool = VoicePool::default();
+        pool.note_on(40, 2, adsr);
+        pool.note_on(45, 2, adsr);
+        assert_eq!(notes(&pool), [40, 45]);
+        pool.note_on(52, 2, adsr);
+        assert_eq!(notes(&pool), [45, 52]);
+
+        // Retriggering a held note reuses its voice
+        pool.note_on(45, 2, adsr);
+        assert_eq!(notes(&pool), [52, 45]);
+    }
+
+    #[test]
+    fn test_voice_pool_removes_released_voices() {
+        let mut pool = VoicePool::default();
+        pool.note_on(40, 4, Adsr::new(0.0, 0.0, 1.0, 0.0));
+        for voice in pool.iter_mut() {
+            voice.adsr.next(1000.0);
+        }
+        pool.note_off(40, 1000.0);
+        for voice in pool.iter_mut() {
+            voice.adsr.next(1000.0);
+        }
+        pool.remove_finished();
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_saw_stack_set_phase_keeps_intervals_aligned() {
+        let mut stack = SawStack::default();
+        stack.set_phase(0.0);
+        assert_eq!(stack, SawStack::default());
+    }
+}
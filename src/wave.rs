@@ -1,17 +1,77 @@
 pub fn saw_wave(phase: f32, terms: u32) -> f32 {
-    let mut sum = 0.0_f32;
+    // `sin(n * phase)` comes from rotating the previous term by `phase` rather than from a `sin`
+    // per term, which keeps long series affordable. The rotation runs in double precision so
+    // the error doesn't build up over a thousand terms.
+    let (step_sin, step_cos) = (phase as f64).sin_cos();
+    let (mut sin, mut cos) = (0.0_f64, 1.0_f64);
+    let mut sum = 0.0_f64;
     for n in 1..=terms {
-        let n_f = n as f32;
+        (sin, cos) = (sin * step_cos + cos * step_sin, cos * step_cos - sin * step_sin);
         // (-1)^(n+1) coefficient
-        let sign = if n % 2 == 0 { -1.0_f32 } else { 1.0_f32 };
-        sum += sign * (phase * n_f).sin() / n_f;
+        let sign = if n % 2 == 0 { -1.0 } else { 1.0 };
+        sum += sign * sin / n as f64;
+    }
+    (2.0 / std::f32::consts::PI) * sum as f32
+}
+
+/// The most `saw_wave` terms that stay at or below Nyquist for a fundamental of `freq`.
+pub fn max_safe_terms(freq: f32, sample_rate: f32) -> u32 {
+    // Float to int casts saturate, so a zero frequency allows any number of terms
+    (sample_rate / (2.0 * freq.abs())).floor() as u32
+}
+
+/// Additive saw with harmonic `n` at an amplitude of `1 / n^slope`. A slope of `1.0` is the
+/// regular `saw_wave`, higher slopes roll off the upper harmonics faster for a darker tone.
+pub fn saw_wave_slope(phase: f32, terms: u32, slope: f32) -> f32 {
+    // The same rotation as `saw_wave`
+    let (step_sin, step_cos) = (phase as f64).sin_cos();
+    let (mut sin, mut cos) = (0.0_f64, 1.0_f64);
+    let mut sum = 0.0_f64;
+    for n in 1..=terms {
+        (sin, cos) = (sin * step_cos + cos * step_sin, cos * step_cos - sin * step_sin);
+        let sign = if n % 2 == 0 { -1.0 } else { 1.0 };
+        sum += sign * sin / (n as f64).powf(slope as f64);
     }
-    (2.0 / std::f32::consts::PI) * sum
+    (2.0 / std::f32::consts::PI) * sum as f32
+}
+
+/// Sum of harmonics `n = 1, 2, ...` at `amplitudes[n - 1]`, each shifted by `offsets[n - 1]`
+/// radians. Missing offsets count as zero.
+pub fn additive_wave(phase: f32, amplitudes: &[f32], offsets: &[f32]) -> f32 {
+    let mut sum = 0.0_f32;
+    for (i, &amplitude) in amplitudes.iter().enumerate() {
+        let offset = offsets.get(i).copied().unwrap_or(0.0);
+        sum += amplitude * (phase * (i + 1) as f32 + offset).sin();
+    }
+    sum
+}
+
+/// Like `additive_wave` without offsets, but harmonic `n` runs at `n * freq * (1 + stretch * n)`.
+/// The stretched partials no longer share a period, so each keeps its own phase in `partials`.
+/// Partials at or above Nyquist are left out.
+pub fn additive_wave_stretch(
+    partials: &mut [Oscillator],
+    amplitudes: &[f32],
+    freq: f32,
+    stretch: f32,
+    sample_rate: f32,
+) -> f32 {
+    let mut sum = 0.0_f32;
+    for (i, (partial, &amplitude)) in partials.iter_mut().zip(amplitudes).enumerate() {
+        let n = (i + 1) as f32;
+        let partial_freq = n * freq * (1.0 + stretch * n);
+        let phase = partial.next(partial_freq, sample_rate);
+        if partial_freq < sample_rate / 2.0 {
+            sum += amplitude * phase.sin();
+        }
+    }
+    sum
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::magnitude_at;
 
     #[test]
     fn test_saw_wave_zero_phase() {
@@ -19,6 +79,14 @@ mod tests {
         assert!(v.abs() < 1e-6);
     }
 
+    #[test]
+    fn test_max_safe_terms() {
+        assert_eq!(max_safe_terms(1000.0, 44100.0), 22);
+        assert_eq!(max_safe_terms(20.0, 48000.0), 1200);
+        assert_eq!(max_safe_terms(-1000.0, 44100.0), 22);
+        assert_eq!(max_safe_terms(30000.0, 44100.0), 0);
+    }
+
     #[test]
     fn test_saw_wave_more_terms() {
         // With a single term this approximates a sine, increasing terms
@@ -42,6 +110,80 @@ mod tests {
         let v = saw_wave(-std::f32::consts::FRAC_PI_2, 200);
         assert!((v + 0.5).abs() < 0.01);
     }
+
+    #[test]
+    fn test_saw_wave_slope_one_matches_saw() {
+        for i in 0..64 {
+            let phase = i as f32 * 0.1;
+            assert_eq!(saw_wave_slope(phase, 10, 1.0), saw_wave(phase, 10));
+        }
+    }
+
+    #[test]
+    fn test_saw_wave_slope_rolls_off_harmonics() {
+        let sample_rate = 44100.0;
+        let render = |slope: f32| -> Vec<f32> {
+            (0..4410)
+                .map(|i| {
+                    let phase = 2.0 * std::f32::consts::PI * 100.0 * i as f32 / sample_rate;
+                    saw_wave_slope(phase, 20, slope)
+                })
+                .collect()
+        };
+        let classic = render(1.0);
+        let dark = render(2.0);
+        let high = |samples: &[f32]| {
+            crate::test_util::band_energy(samples, sample_rate, 500.0, 2500.0)
+        };
+        assert!(high(&dark) < high(&classic) * 0.5);
+    }
+
+    #[test]
+    fn test_additive_wave_stretch() {
+        let sample_rate = 44100.0;
+        let amplitudes = [1.0, 0.5, 0.25];
+        let mut fundamental = Oscillator::default();
+        let mut partials = [Oscillator::default(); 3];
+        for _ in 0..4410 {
+            let phase = fundamental.next(100.0, sample_rate);
+            let stretched =
+                additive_wave_stretch(&mut partials, &amplitudes, 100.0, 0.0, sample_rate);
+            assert!((stretched - additive_wave(phase, &amplitudes, &[])).abs() < 1e-3);
+        }
+
+        // With a stretch of 0.05 the third harmonic moves from 300 Hz up to 345 Hz
+        let mut partials = [Oscillator::default(); 3];
+        let amplitudes = [0.0, 0.0, 1.0];
+        let third: Vec<f32> = (0..44100)
+            .map(|_| additive_wave_stretch(&mut partials, &amplitudes, 100.0, 0.05, sample_rate))
+            .collect();
+        assert!(magnitude_at(&third, sample_rate, 345.0) > 0.9);
+        assert!(magnitude_at(&third, sample_rate, 300.0) < 0.1);
+    }
+
+    #[test]
+    fn test_additive_wave_offsets_lower_crest_factor() {
+        let crest_factor = |offsets: &[f32]| {
+            let amplitudes = [1.0; 8];
+            let samples: Vec<f32> = (0..4096)
+                .map(|i| {
+                    let phase = 2.0 * std::f32::consts::PI * i as f32 / 4096.0;
+                    additive_wave(phase, &amplitudes, offsets)
+                })
+                .collect();
+            let peak = samples.iter().fold(0.0_f32, |acc, s| acc.max(s.abs()));
+            let rms = (samples.iter().map(|s| s * s).sum::<f32>() / 4096.0).sqrt();
+            peak / rms
+        };
+        let mut rng = crate::XorShift32::default();
+        let offsets: Vec<f32> = (0..8)
+            .map(|_| rng.next_f32() * 2.0 * std::f32::consts::PI)
+            .collect();
+        assert!(crest_factor(&offsets) < crest_factor(&[]));
+        // Without offsets this is the same series `saw_wave` sums
+        let saw = [1.0, -0.5, 1.0 / 3.0].map(|a| a * std::f32::consts::FRAC_2_PI);
+        assert!((additive_wave(1.0, &saw, &[]) - saw_wave(1.0, 3)).abs() < 1e-6);
+    }
 }
 
 pub fn sine_wave(freq: f32, sample_rate: f32, sample_index: usize) -> f32 {
@@ -68,6 +210,157 @@ pub fn saw_with_gain(freq: f32, sample_rate: f32, terms: u32, curve: &[f32]) ->
         .collect()
 }
 
+/// Phase accumulator for the generated oscillators. The phase is advanced by the instantaneous
+/// frequency on every sample, so any frequency trajectory stays phase continuous. It's stored in
+/// double precision for `next_f64`, `next` rounds it to single precision on every step.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Oscillator {
+    phase: f64,
+}
+
+impl Oscillator {
+    pub fn new(phase: f32) -> Self {
+        Self {
+            phase: phase.rem_euclid(2.0 * std::f32::consts::PI) as f64,
+        }
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase as f32
+    }
+
+    /// Move the phase by `delta` radians without otherwise disturbing the accumulator.
+    pub fn shift(&mut self, delta: f32) {
+        self.phase = (self.phase + delta as f64).rem_euclid(2.0 * std::f64::consts::PI);
+    }
+
+    /// Return the phase in radians for the current sample and advance it by `freq`.
+    pub fn next(&mut self, freq: f32, sample_rate: f32) -> f32 {
+        let phase = self.phase as f32;
+        let phase_inc = 2.0 * std::f32::consts::PI * freq / sample_rate;
+        self.phase = (phase + phase_inc).rem_euclid(2.0 * std::f32::consts::PI) as f64;
+        phase
+    }
+
+    /// Like `next`, but the phase is accumulated in double precision so it doesn't drift over
+    /// long renders.
+    pub fn next_f64(&mut self, freq: f64, sample_rate: f64) -> f32 {
+        let phase = self.phase;
+        let phase_inc = 2.0 * std::f64::consts::PI * freq / sample_rate;
+        self.phase = (phase + phase_inc).rem_euclid(2.0 * std::f64::consts::PI);
+        phase as f32
+    }
+}
+
+/// Single cycle waveform read with linear interpolation, one cycle spans a phase of `0..2π`
+/// like the other oscillators.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wavetable {
+    table: Vec<f32>,
+}
+
+impl Wavetable {
+    /// Load one cycle, the table wraps around from its last sample back to the first.
+    pub fn new(table: Vec<f32>) -> Self {
+        Self { table }
+    }
+
+    pub fn table(&self) -> &[f32] {
+        &self.table
+    }
+
+    /// The waveform at `phase` radians, silence for an empty table.
+    pub fn sample(&self, phase: f32) -> f32 {
+        let len = self.table.len();
+        if len == 0 {
+            return 0.0;
+        }
+        let pos = phase.rem_euclid(2.0 * std::f32::consts::PI) / (2.0 * std::f32::consts::PI)
+            * len as f32;
+        let index = (pos as usize).min(len - 1);
+        let fraction = pos - index as f32;
+        let current = self.table[index];
+        current + fraction * (self.table[(index + 1) % len] - current)
+    }
+}
+
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Residual of a step smoothed with a cubic B-spline, twice as wide as `poly_blep` so it spreads
+/// the correction over two samples on either side of the discontinuity.
+fn poly_blep_wide(t: f32, dt: f32) -> f32 {
+    // Integral of the cubic B-spline for `x` from -2 to 0 samples before the step
+    let rise = |x: f32| {
+        if x < -1.0 {
+            (2.0 + x).powi(4) / 24.0
+        } else {
+            (4.0 * x - 2.0 * x.powi(3) - 0.75 * x.powi(4) + 2.75) / 6.0 + 1.0 / 24.0
+        }
+    };
+    if t < 2.0 * dt {
+        -2.0 * rise(-t / dt)
+    } else if t > 1.0 - 2.0 * dt {
+        2.0 * rise((t - 1.0) / dt)
+    } else {
+        0.0
+    }
+}
+
+/// Band limited saw using PolyBLEP correction around the discontinuity. Uses the same phase
+/// convention as `saw_wave`, `phase_inc` is the phase advance per sample in radians. A `width`
+/// of 2 spreads the correction over two samples per side instead of one, which costs a little
+/// more and leaves less aliasing at high pitches.
+pub fn saw_wave_polyblep(phase: f32, phase_inc: f32, width: u32) -> f32 {
+    let t = (phase / (2.0 * std::f32::consts::PI) + 0.5).rem_euclid(1.0);
+    let dt = (phase_inc / (2.0 * std::f32::consts::PI)).abs();
+    if width >= 2 {
+        (2.0 * t - 1.0) - poly_blep_wide(t, dt.min(0.25))
+    } else {
+        (2.0 * t - 1.0) - poly_blep(t, dt.min(0.5))
+    }
+}
+
+/// Naive ramp from -1 at a phase of 0 to 1 at 2π. Aliases badly at higher frequencies but costs
+/// next to nothing, which is fine for pure sub frequencies. Note that this resets at 0 rather
+/// than at π like `saw_wave`.
+pub fn saw_ramp(phase: f32) -> f32 {
+    phase.rem_euclid(2.0 * std::f32::consts::PI) / std::f32::consts::PI - 1.0
+}
+
+/// Highest input frequency the octave divider reacts to, faster crossings are treated as noise.
+const DIVIDER_MAX_HZ: f32 = 2000.0;
+
+/// Classic analog style octave divider. A square wave flips on every positive going zero
+/// crossing of the input, so it runs at exactly half the input frequency and stays locked to it.
+/// The output is silent until the first crossing.
+pub fn octave_divider(samples: &[f32], sample_rate: f32) -> Vec<f32> {
+    let min_interval = (sample_rate / DIVIDER_MAX_HZ) as usize;
+    let mut out = Vec::with_capacity(samples.len());
+    let mut state = 0.0_f32;
+    let mut previous = 0.0_f32;
+    let mut since_crossing = usize::MAX;
+    for &s in samples {
+        since_crossing = since_crossing.saturating_add(1);
+        if previous <= 0.0 && s > 0.0 && since_crossing >= min_interval {
+            state = if state > 0.0 { -1.0 } else { 1.0 };
+            since_crossing = 0;
+        }
+        previous = s;
+        out.push(state);
+    }
+    out
+}
+
 #[cfg(test)]
 mod modulated_tests {
     use super::*;
@@ -93,6 +386,28 @@ mod modulated_tests {
         assert_eq!(out.len(), curve.len());
     }
 
+    #[test]
+    fn test_octave_divider_halves_frequency() {
+        let sample_rate = 44100.0;
+        let input: Vec<f32> = (0..44100)
+            .map(|i| (2.0 * std::f32::consts::PI * 100.0 * i as f32 / sample_rate).sin())
+            .collect();
+        let out = octave_divider(&input, sample_rate);
+
+        // One rising edge per two input cycles
+        let rising = out.windows(2).filter(|w| w[0] < 0.0 && w[1] > 0.0).count();
+        assert!((49..=50).contains(&rising));
+        let sub = crate::test_util::magnitude_at(&out, sample_rate, 50.0);
+        let fundamental = crate::test_util::magnitude_at(&out, sample_rate, 100.0);
+        assert!(sub > 1.0);
+        assert!(fundamental < 0.01);
+    }
+
+    #[test]
+    fn test_octave_divider_silence() {
+        assert!(octave_divider(&[0.0; 64], 44100.0).iter().all(|&s| s == 0.0));
+    }
+
     #[test]
     fn test_saw_with_gain_values() {
         // Constant gain should produce a non-zero waveform matching `saw_wave`
@@ -107,3 +422,120 @@ mod modulated_tests {
         assert_eq!(out, expected);
     }
 }
+
+#[cfg(test)]
+mod oscillator_tests {
+    use super::*;
+
+    #[test]
+    fn test_oscillator_wraps_phase() {
+        let mut osc = Oscillator::default();
+        assert_eq!(osc.next(1.0, 4.0), 0.0);
+        for _ in 0..10 {
+            let phase = osc.next(1.0, 4.0);
+            assert!((0.0..2.0 * std::f32::consts::PI).contains(&phase));
+        }
+    }
+
+    #[test]
+    fn test_oscillator_sweep_is_phase_continuous() {
+        // Linear sweep from 20 Hz to 2 kHz, every phase step should match the instantaneous
+        // frequency of the previous sample
+        let sample_rate = 44100.0;
+        let len = 4096;
+        let freqs: Vec<f32> = (0..len)
+            .map(|i| 20.0 + 1980.0 * i as f32 / len as f32)
+            .collect();
+        let mut osc = Oscillator::default();
+        let phases: Vec<f32> = freqs.iter().map(|&f| osc.next(f, sample_rate)).collect();
+        for i in 1..len {
+            let step = (phases[i] - phases[i - 1]).rem_euclid(2.0 * std::f32::consts::PI);
+            let expected = 2.0 * std::f32::consts::PI * freqs[i - 1] / sample_rate;
+            assert!((step - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_wavetable_interpolates_and_wraps() {
+        let table = Wavetable::new(vec![0.0, 1.0, 0.0, -1.0]);
+        assert_eq!(table.sample(0.0), 0.0);
+        assert_eq!(table.sample(0.5 * std::f32::consts::PI), 1.0);
+        assert!((table.sample(0.25 * std::f32::consts::PI) - 0.5).abs() < 1e-6);
+        // Between the last sample and the wrapped around first one
+        assert!((table.sample(-0.25 * std::f32::consts::PI) + 0.5).abs() < 1e-6);
+        assert_eq!(Wavetable::new(Vec::new()).sample(1.0), 0.0);
+    }
+}
+
+#[cfg(test)]
+mod polyblep_tests {
+    use super::*;
+    use crate::test_util::band_energy;
+
+    fn render(freq: f32, sample_rate: f32, len: usize, osc: impl Fn(f32, f32) -> f32) -> Vec<f32> {
+        let phase_inc = 2.0 * std::f32::consts::PI * freq / sample_rate;
+        let mut phase = 0.0_f32;
+        (0..len)
+            .map(|_| {
+                let s = osc(phase, phase_inc);
+                phase = (phase + phase_inc) % (2.0 * std::f32::consts::PI);
+                s
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_saw_wave_polyblep_matches_saw_shape() {
+        // Away from the discontinuity the PolyBLEP saw is the plain ramp
+        let inc = 0.01;
+        for width in [1, 2] {
+            assert!(saw_wave_polyblep(0.0, inc, width).abs() < 1e-6);
+            let quarter = std::f32::consts::FRAC_PI_2;
+            assert!((saw_wave_polyblep(quarter, inc, width) - 0.5).abs() < 1e-6);
+            assert!((saw_wave_polyblep(-quarter, inc, width) + 0.5).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_saw_ramp() {
+        assert_eq!(saw_ramp(0.0), -1.0);
+        assert!(saw_ramp(std::f32::consts::PI).abs() < 1e-6);
+        let ramp: Vec<f32> = (0..100)
+            .map(|i| saw_ramp(i as f32 * 2.0 * std::f32::consts::PI / 100.0))
+            .collect();
+        assert!(ramp.windows(2).all(|w| w[1] > w[0]));
+        assert!(*ramp.last().unwrap() < 1.0);
+    }
+
+    #[test]
+    fn test_saw_wave_polyblep_less_aliasing() {
+        // Compared to the naive ramp it corrects, the PolyBLEP saw should put less energy in the
+        // upper half of the spectrum
+        let sample_rate = 44100.0;
+        let naive = render(5000.0, sample_rate, 4410, |phase, _| saw_ramp(phase));
+        let blep = render(5000.0, sample_rate, 4410, |phase, inc| {
+            saw_wave_polyblep(phase, inc, 1)
+        });
+        let nyquist = sample_rate / 2.0;
+        let naive_high = band_energy(&naive, sample_rate, nyquist / 2.0, nyquist);
+        let blep_high = band_energy(&blep, sample_rate, nyquist / 2.0, nyquist);
+        assert!(blep_high < naive_high);
+    }
+
+    #[test]
+    fn test_saw_wave_polyblep_wide_less_aliasing() {
+        // Harmonics 5 to 9 of a 5 kHz saw fold back to these frequencies, between the harmonics
+        let sample_rate = 44100.0;
+        let aliases = [19100.0, 14100.0, 9100.0, 4100.0, 900.0];
+        let alias_level = |width: u32| -> f32 {
+            let saw = render(5000.0, sample_rate, 4410, |phase, inc| {
+                saw_wave_polyblep(phase, inc, width)
+            });
+            aliases
+                .iter()
+                .map(|&freq| crate::test_util::magnitude_at(&saw, sample_rate, freq))
+                .sum()
+        };
+        assert!(alias_level(2) < 0.5 * alias_level(1));
+    }
+}